@@ -0,0 +1,608 @@
+use crate::error::{IndexerError, Result};
+use axum::async_trait;
+use hashlink::LruCache;
+use log::{info, warn};
+use rand::Rng;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Client, SetExpiry, SetOptions};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{watch, Mutex};
+
+const DEFAULT_CONTRACT_TTL: u64 = 600; // 10 min
+const DEFAULT_VALIDATOR_DATA_TTL: u64 = 60;
+const DEFAULT_VALIDATOR_STATUS_TTL: u64 = 15;
+const DEFAULT_STALE: u64 = 300;
+const DEFAULT_MEMORY_CAPACITY: usize = 10_000;
+/// Redis operations slower than this are treated as a miss
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(500);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+const CONNECT_RETRIES: usize = 2;
+/// Pause between two connection attempts while Redis is unreachable
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Seconds a cached contract endpoint response lives, `0` disables caching of the endpoint.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub bound: u64,
+    pub reward: u64,
+    pub debt: u64,
+    pub vdata: u64,
+    pub vstatus: u64,
+    /// Seconds an expired response is still served while it is refreshed in the background
+    pub stale: u64,
+    /// Maximum number of entries of the in-process cache, used without `redis_url`
+    pub memory_capacity: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            bound: DEFAULT_CONTRACT_TTL,
+            reward: DEFAULT_CONTRACT_TTL,
+            debt: DEFAULT_CONTRACT_TTL,
+            vdata: DEFAULT_VALIDATOR_DATA_TTL,
+            vstatus: DEFAULT_VALIDATOR_STATUS_TTL,
+            stale: DEFAULT_STALE,
+            memory_capacity: DEFAULT_MEMORY_CAPACITY,
+        }
+    }
+}
+
+/// Storage of serialized cache entries. A backend that fails to answer reports a miss.
+#[async_trait]
+pub trait CacheBackend: Send + Sync {
+    async fn get(&self, key: &str) -> Option<String>;
+    async fn set(&self, key: &str, value: String, ttl: Duration);
+    async fn delete(&self, keys: &[String]);
+}
+
+#[derive(Default)]
+struct Connection {
+    manager: Option<ConnectionManager>,
+    failed_at: Option<Instant>,
+}
+
+/// Redis shared by all indexer instances of a deployment.
+pub struct RedisBackend {
+    client: Client,
+    conn: Mutex<Connection>,
+}
+
+impl RedisBackend {
+    pub fn new(redis_url: &str) -> Result<Self> {
+        Ok(RedisBackend {
+            client: Client::open(redis_url)?,
+            conn: Mutex::new(Connection::default()),
+        })
+    }
+
+    /// Multiplexed connection shared by all handlers, established on first use. Once up, the
+    /// manager reconnects by itself.
+    async fn connection(&self) -> Option<ConnectionManager> {
+        let mut conn = self.conn.lock().await;
+        if let Some(manager) = &conn.manager {
+            return Some(manager.clone());
+        }
+        if conn
+            .failed_at
+            .is_some_and(|t| t.elapsed() < RECONNECT_INTERVAL)
+        {
+            return None;
+        }
+
+        match ConnectionManager::new_with_backoff_and_timeouts(
+            self.client.clone(),
+            2,
+            100,
+            CONNECT_RETRIES,
+            RESPONSE_TIMEOUT,
+            CONNECT_TIMEOUT,
+        )
+        .await
+        {
+            Ok(manager) => {
+                conn.manager = Some(manager.clone());
+                conn.failed_at = None;
+                Some(manager)
+            }
+            Err(e) => {
+                warn!("redis unavailable, serving without cache: {}", e);
+                conn.failed_at = Some(Instant::now());
+                None
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl CacheBackend for RedisBackend {
+    async fn get(&self, key: &str) -> Option<String> {
+        let mut conn = self.connection().await?;
+        match conn.get(key).await {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("cache get {}: {}", key, e);
+                None
+            }
+        }
+    }
+
+    async fn set(&self, key: &str, value: String, ttl: Duration) {
+        let Some(mut conn) = self.connection().await else {
+            return;
+        };
+        let opts = SetOptions::default().with_expiration(SetExpiry::PX(ttl.as_millis() as usize));
+        if let Err(e) = conn.set_options::<_, _, ()>(key, value, opts).await {
+            warn!("cache set {}: {}", key, e);
+        }
+    }
+
+    async fn delete(&self, keys: &[String]) {
+        let Some(mut conn) = self.connection().await else {
+            return;
+        };
+        if let Err(e) = conn.del::<_, ()>(keys).await {
+            warn!("cache delete {:?}: {}", keys, e);
+        }
+    }
+}
+
+/// In-process LRU cache for single instance deployments.
+pub struct MemoryBackend {
+    entries: std::sync::Mutex<LruCache<String, (String, Instant)>>,
+}
+
+impl MemoryBackend {
+    pub fn new(capacity: usize) -> Self {
+        MemoryBackend {
+            entries: std::sync::Mutex::new(LruCache::new(capacity)),
+        }
+    }
+}
+
+#[async_trait]
+impl CacheBackend for MemoryBackend {
+    async fn get(&self, key: &str) -> Option<String> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((value, expires)) if *expires > Instant::now() => Some(value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    async fn set(&self, key: &str, value: String, ttl: Duration) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(key.to_string(), (value, Instant::now() + ttl));
+    }
+
+    async fn delete(&self, keys: &[String]) {
+        let mut entries = self.entries.lock().unwrap();
+        for key in keys {
+            entries.remove(key);
+        }
+    }
+}
+
+/// Cached value with the time, in unix milliseconds, it is due for a refresh.
+#[derive(Serialize, Deserialize)]
+struct Entry<T> {
+    fresh_until: u64,
+    value: T,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Result shared with the waiters of a flight, the JSON of the value or the fetch error.
+type Outcome = Option<std::result::Result<String, String>>;
+
+/// Fetch of a key in progress. Waiters receive its outcome, the sender is dropped without
+/// one if the fetch is cancelled or its key is evicted meanwhile.
+struct Flight {
+    /// Unique per flight, an evicted key gets a new flight with a new id
+    id: u64,
+    receiver: watch::Receiver<Outcome>,
+}
+
+type Flights = Arc<std::sync::Mutex<HashMap<String, Flight>>>;
+
+enum Joined {
+    Leader(Leader),
+    Follower(watch::Receiver<Outcome>),
+}
+
+/// Fetch of `key` run by this task, it ends the flight however the fetch finishes.
+struct Leader {
+    flights: Flights,
+    key: String,
+    id: u64,
+    sender: watch::Sender<Outcome>,
+}
+
+impl Leader {
+    /// Whether `key` was evicted since the flight started, its result may predate the change.
+    fn evicted(&self) -> bool {
+        self.flights
+            .lock()
+            .unwrap()
+            .get(&self.key)
+            .is_none_or(|f| f.id != self.id)
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        let mut flights = self.flights.lock().unwrap();
+        if flights.get(&self.key).is_some_and(|f| f.id == self.id) {
+            flights.remove(&self.key);
+        }
+    }
+}
+
+/// JSON cache in front of contract calls. A miss, whatever its cause, is served straight
+/// from the source. Concurrent misses of a key share one fetch, and an expired value is
+/// still served for `stale` seconds while a background task refreshes it.
+pub struct Cache {
+    backend: Box<dyn CacheBackend>,
+    flights: Flights,
+    next_flight: AtomicU64,
+    pub ttl: CacheConfig,
+}
+
+impl Cache {
+    /// Redis when `redis_url` is set, the in-process cache otherwise.
+    pub fn new(redis_url: Option<&str>, ttl: CacheConfig) -> Result<Self> {
+        let backend: Box<dyn CacheBackend> = match redis_url {
+            Some(url) => Box::new(RedisBackend::new(url)?),
+            None => {
+                info!("No redis configured, caching in memory");
+                Box::new(MemoryBackend::new(ttl.memory_capacity))
+            }
+        };
+        Ok(Cache {
+            backend,
+            flights: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            next_flight: AtomicU64::new(0),
+            ttl,
+        })
+    }
+
+    async fn get<T: DeserializeOwned>(&self, key: &str) -> Option<Entry<T>> {
+        let data = self.backend.get(key).await?;
+        match serde_json::from_str(&data) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                warn!("cache entry {} is corrupt: {}", key, e);
+                None
+            }
+        }
+    }
+
+    async fn set<T: Serialize>(&self, key: &str, value: &T, ttl: u64) {
+        // up to 10% later, so entries written together don't expire together
+        let fresh_ms = ttl * 1000 + rand::thread_rng().gen_range(0..=ttl * 100);
+        let entry = Entry {
+            fresh_until: now_ms() + fresh_ms,
+            value,
+        };
+        let data = match serde_json::to_string(&entry) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("cache set {}: {}", key, e);
+                return;
+            }
+        };
+        let keep = Duration::from_millis(fresh_ms) + Duration::from_secs(self.ttl.stale);
+        self.backend.set(key, data, keep).await;
+    }
+
+    /// Deletes `keys`. Fetches of them already in flight read the source before the change,
+    /// their results are not cached.
+    pub async fn evict(&self, keys: &[String]) {
+        if keys.is_empty() {
+            return;
+        }
+        {
+            let mut flights = self.flights.lock().unwrap();
+            for key in keys {
+                flights.remove(key);
+            }
+        }
+        self.backend.delete(keys).await;
+    }
+
+    fn start(&self, flights: &mut HashMap<String, Flight>, key: &str) -> Leader {
+        let id = self.next_flight.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = watch::channel(None);
+        flights.insert(key.to_string(), Flight { id, receiver });
+        Leader {
+            flights: self.flights.clone(),
+            key: key.to_string(),
+            id,
+            sender,
+        }
+    }
+
+    fn join(&self, key: &str) -> Joined {
+        let mut flights = self.flights.lock().unwrap();
+        match flights.get(key) {
+            Some(flight) => Joined::Follower(flight.receiver.clone()),
+            None => Joined::Leader(self.start(&mut flights, key)),
+        }
+    }
+
+    /// Starts a flight of `key` unless one is in progress.
+    fn lead(&self, key: &str) -> Option<Leader> {
+        let mut flights = self.flights.lock().unwrap();
+        if flights.contains_key(key) {
+            return None;
+        }
+        Some(self.start(&mut flights, key))
+    }
+
+    /// Fetches and caches `key`, or waits for the fetch already in progress and shares its
+    /// result or error. Waiters of a cancelled or evicted fetch join the next one.
+    async fn fetch<T, F, Fut>(&self, key: &str, ttl: u64, fetch: F) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        loop {
+            match self.join(key) {
+                Joined::Leader(leader) => return self.run(leader, ttl, fetch).await,
+                Joined::Follower(mut flight) => {
+                    if flight.changed().await.is_ok() {
+                        let outcome = flight.borrow().clone();
+                        match outcome {
+                            Some(Ok(data)) => {
+                                if let Ok(value) = serde_json::from_str(&data) {
+                                    return Ok(value);
+                                }
+                            }
+                            Some(Err(e)) => return Err(IndexerError::IndexerCustom(e)),
+                            None => {}
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs the fetch of `leader` and caches its result unless the key was evicted meanwhile.
+    async fn run<T, F, Fut>(&self, leader: Leader, ttl: u64, fetch: F) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let value = match fetch().await {
+            Ok(value) => value,
+            Err(e) => {
+                // A failure predating an eviction may not hold anymore, its waiters retry.
+                if !leader.evicted() {
+                    let _ = leader.sender.send(Some(Err(e.to_string())));
+                }
+                return Err(e);
+            }
+        };
+        if leader.evicted() {
+            return Ok(value);
+        }
+        self.set(&leader.key, &value, ttl).await;
+        // An eviction between the check and the write may have deleted the key before it.
+        if leader.evicted() {
+            self.backend.delete(std::slice::from_ref(&leader.key)).await;
+        } else if let Ok(data) = serde_json::to_string(&value) {
+            let _ = leader.sender.send(Some(Ok(data)));
+        }
+        Ok(value)
+    }
+
+    /// Cached value of `key`, or the result of `fetch` which is then cached for `ttl`
+    /// seconds. Errors of `fetch` are returned and not cached.
+    pub async fn get_or_fetch<T, F, Fut>(
+        self: &Arc<Self>,
+        key: &str,
+        ttl: u64,
+        fetch: F,
+    ) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync + 'static,
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        if ttl == 0 {
+            return fetch().await;
+        }
+        let Some(entry) = self.get::<T>(key).await else {
+            return self.fetch(key, ttl, fetch).await;
+        };
+        if entry.fresh_until <= now_ms() {
+            if let Some(leader) = self.lead(key) {
+                let cache = self.clone();
+                let key = key.to_string();
+                tokio::spawn(async move {
+                    if let Err(e) = cache.run(leader, ttl, fetch).await {
+                        warn!("refresh {}: {}", key, e);
+                    }
+                });
+            }
+        }
+        Ok(entry.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[tokio::test]
+    async fn falls_back_without_redis() {
+        let cache =
+            Arc::new(Cache::new(Some("redis://127.0.0.1:1"), CacheConfig::default()).unwrap());
+        let v: u64 = cache
+            .get_or_fetch("k", 60, || async { Ok(7) })
+            .await
+            .unwrap();
+        assert_eq!(v, 7);
+
+        let r: Result<u64> = cache
+            .get_or_fetch("k", 60, || async {
+                Err(IndexerError::IndexerCustom("rpc".into()))
+            })
+            .await;
+        assert!(r.is_err());
+    }
+
+    #[tokio::test]
+    async fn memory_hit_expiry_and_eviction() {
+        let cache = Arc::new(Cache::new(None, CacheConfig::default()).unwrap());
+        let v: u64 = cache
+            .get_or_fetch("k", 60, || async { Ok(7) })
+            .await
+            .unwrap();
+        assert_eq!(v, 7);
+        let v: u64 = cache
+            .get_or_fetch("k", 60, || async { Ok(8) })
+            .await
+            .unwrap();
+        assert_eq!(v, 7);
+
+        let memory = MemoryBackend::new(2);
+        memory.set("a", "1".into(), Duration::from_secs(60)).await;
+        memory.set("b", "2".into(), Duration::ZERO).await;
+        assert_eq!(memory.get("b").await, None);
+        memory.set("b", "2".into(), Duration::from_secs(60)).await;
+        assert_eq!(memory.get("a").await.as_deref(), Some("1"));
+        memory.set("c", "3".into(), Duration::from_secs(60)).await;
+        assert_eq!(memory.get("b").await, None);
+        assert_eq!(memory.get("a").await.as_deref(), Some("1"));
+        memory.delete(&["a".into()]).await;
+        assert_eq!(memory.get("a").await, None);
+    }
+
+    #[tokio::test]
+    async fn concurrent_misses_fetch_once() {
+        let cache = Arc::new(Cache::new(None, CacheConfig::default()).unwrap());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let tasks = (0..10)
+            .map(|_| {
+                let cache = cache.clone();
+                let calls = calls.clone();
+                tokio::spawn(async move {
+                    cache
+                        .get_or_fetch("k", 60, move || async move {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            Ok(7u64)
+                        })
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            assert_eq!(task.await.unwrap().unwrap(), 7);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn concurrent_misses_share_failure() {
+        let cache = Arc::new(Cache::new(None, CacheConfig::default()).unwrap());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let tasks = (0..10)
+            .map(|_| {
+                let cache = cache.clone();
+                let calls = calls.clone();
+                tokio::spawn(async move {
+                    cache
+                        .get_or_fetch::<u64, _, _>("k", 60, move || async move {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            Err(IndexerError::IndexerCustom("rpc".into()))
+                        })
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            assert_eq!(task.await.unwrap().unwrap_err().to_string(), "rpc");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn serves_stale_while_refreshing() {
+        let cache = Arc::new(Cache::new(None, CacheConfig::default()).unwrap());
+        let expired = Entry {
+            fresh_until: now_ms() - 1,
+            value: 7u64,
+        };
+        let data = serde_json::to_string(&expired).unwrap();
+        cache.backend.set("k", data, Duration::from_secs(60)).await;
+
+        let v: u64 = cache
+            .get_or_fetch("k", 60, || async { Ok(8) })
+            .await
+            .unwrap();
+        assert_eq!(v, 7);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let v: u64 = cache
+            .get_or_fetch("k", 60, || async { Ok(9) })
+            .await
+            .unwrap();
+        assert_eq!(v, 8);
+    }
+
+    #[tokio::test]
+    async fn eviction_drops_refresh_in_flight() {
+        let cache = Arc::new(Cache::new(None, CacheConfig::default()).unwrap());
+        let expired = Entry {
+            fresh_until: now_ms() - 1,
+            value: 7u64,
+        };
+        let data = serde_json::to_string(&expired).unwrap();
+        cache.backend.set("k", data, Duration::from_secs(60)).await;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        for _ in 0..5 {
+            let calls = calls.clone();
+            let v: u64 = cache
+                .get_or_fetch("k", 60, move || async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    Ok(8)
+                })
+                .await
+                .unwrap();
+            assert_eq!(v, 7);
+        }
+        cache.evict(&["k".to_string()]).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.backend.get("k").await, None);
+
+        let v: u64 = cache
+            .get_or_fetch("k", 60, || async { Ok(9) })
+            .await
+            .unwrap();
+        assert_eq!(v, 9);
+    }
+}
@@ -1,202 +1,309 @@
-use crate::error::{IndexerError, Result};
+use crate::error::Result;
+use crate::params::{Address, Query};
 use crate::types::{
-    BoundResponse, DebtResponse, DelegatorSumResponse, RewardResponse, ValidatorDataResponse,
-    ValidatorStatusResponse,
+    BoundResponse, DebtResponse, DelegatorSumResponse, ErrorResponse, RewardResponse,
+    ValidatorDataResponse, ValidatorStatusResponse,
 };
 use crate::AppState;
-use axum::extract::{Query, State};
+use axum::extract::State;
 use axum::Json;
-use ethers::types::H160;
 use ethers::utils::hex;
-use rand::Rng;
-use redis::{Commands, Connection, RedisResult, SetExpiry, SetOptions};
 use serde::{Deserialize, Serialize};
 use sqlx::types::BigDecimal;
 use sqlx::Row;
-use std::str::FromStr;
 use std::sync::Arc;
+use utoipa::IntoParams;
 
 const KEY_BOUND_PREFIX: &str = "E:BND";
 const KEY_REWARD_PREFIX: &str = "E:RWD";
+const KEY_DEBT_PREFIX: &str = "E:DBT";
+const KEY_VALIDATOR_DATA_PREFIX: &str = "E:VDT";
+const KEY_VALIDATOR_STATUS_PREFIX: &str = "E:VST";
 
-#[derive(Serialize, Deserialize)]
+fn bound_key(validator: &Address, delegator: &Address) -> String {
+    format!(
+        "{}:{}:{}",
+        KEY_BOUND_PREFIX,
+        delegator.as_str(),
+        validator.as_str()
+    )
+}
+
+fn reward_key(delegator: &Address) -> String {
+    format!("{}:{}", KEY_REWARD_PREFIX, delegator.as_str())
+}
+
+fn debt_key(validator: &Address, delegator: &Address) -> String {
+    format!(
+        "{}:{}:{}",
+        KEY_DEBT_PREFIX,
+        delegator.as_str(),
+        validator.as_str()
+    )
+}
+
+/// Cache keys of the responses a delegation, undelegation or reward claim of the pair changes.
+pub fn delegator_keys(validator: &Address, delegator: &Address) -> Vec<String> {
+    vec![
+        bound_key(validator, delegator),
+        reward_key(delegator),
+        debt_key(validator, delegator),
+    ]
+}
+
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ValidatorStatusParams {
-    pub address: String,
+    pub address: Address,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/vstatus",
+    tag = "contract",
+    params(ValidatorStatusParams),
+    responses(
+        (status = 200, body = ValidatorStatusResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 pub async fn get_validator_status(
     State(state): State<Arc<AppState>>,
     params: Query<ValidatorStatusParams>,
 ) -> Result<Json<ValidatorStatusResponse>> {
-    let staking = state.staking.clone();
-    let validator = H160::from_str(&params.address)?;
-
-    match staking.validator_status(validator).call().await {
-        Ok(data) => {
-            let res = ValidatorStatusResponse {
+    let validator = params.address.h160();
+    let key = format!(
+        "{}:{}",
+        KEY_VALIDATOR_STATUS_PREFIX,
+        params.address.as_str()
+    );
+    let cache = state.cache.clone();
+    let ttl = state.cache.ttl.vstatus;
+    let res = cache
+        .get_or_fetch(&key, ttl, move || async move {
+            let data = state.staking.validator_status(validator).call().await?;
+            Ok(ValidatorStatusResponse {
                 heap_index_off1: data.0.to_string(),
                 is_active: data.1,
                 jailed: data.2,
                 unjail_datetime: data.3,
                 should_vote: data.4,
                 voted: data.5,
-            };
-            Ok(Json(res))
-        }
-        Err(e) => Err(IndexerError::IndexerCustom(e.to_string())),
-    }
+            })
+        })
+        .await?;
+
+    Ok(Json(res))
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ValidatorDataParams {
-    pub address: String,
+    pub address: Address,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/vdata",
+    tag = "contract",
+    params(ValidatorDataParams),
+    responses(
+        (status = 200, body = ValidatorDataResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 pub async fn get_validator_data(
     State(state): State<Arc<AppState>>,
     params: Query<ValidatorDataParams>,
 ) -> Result<Json<ValidatorDataResponse>> {
-    let staking = state.staking.clone();
-    let validator = H160::from_str(&params.address)?;
-
-    match staking.validators(validator).call().await {
-        Ok(data) => {
-            let res = ValidatorDataResponse {
+    let validator = params.address.h160();
+    let key = format!("{}:{}", KEY_VALIDATOR_DATA_PREFIX, params.address.as_str());
+    let cache = state.cache.clone();
+    let ttl = state.cache.ttl.vdata;
+    let res = cache
+        .get_or_fetch(&key, ttl, move || async move {
+            let data = state.staking.validators(validator).call().await?;
+            Ok(ValidatorDataResponse {
                 public_key: data.0.to_string(),
                 public_key_type: data.1,
                 rate: data.2.to_string(),
-                staker: hex::encode_prefixed(&data.3.as_bytes()),
+                staker: hex::encode_prefixed(data.3.as_bytes()),
                 power: data.4.to_string(),
                 total_unbound_amount: data.5.to_string(),
                 begin_block: data.6.as_u64(),
-            };
-            Ok(Json(res))
-        }
-        Err(e) => Err(IndexerError::IndexerCustom(e.to_string())),
-    }
+            })
+        })
+        .await?;
+
+    Ok(Json(res))
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct DelegatorBoundParams {
-    pub validator: String,
-    pub delegator: String,
+    pub validator: Address,
+    pub delegator: Address,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/bound",
+    tag = "contract",
+    params(DelegatorBoundParams),
+    responses(
+        (status = 200, body = BoundResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 pub async fn get_delegator_bound(
     State(state): State<Arc<AppState>>,
     params: Query<DelegatorBoundParams>,
 ) -> Result<Json<BoundResponse>> {
-    let validator = H160::from_str(&params.validator)?;
-    let delegator = H160::from_str(&params.delegator)?;
-    let mut conn = state.redis.clone().get_connection()?;
-    let key = format!("{}:{:?}:{:?}", KEY_BOUND_PREFIX, delegator, validator);
-    let r: RedisResult<String> = conn.get(&key);
-    match r {
-        Ok(data) => {
-            let resp: BoundResponse = serde_json::from_str(&data).unwrap();
-            Ok(Json(resp))
-        }
-        Err(_) => {
-            let staking = state.staking.clone();
-            match staking.delegators(validator, delegator).call().await {
-                Ok((bound, unbound)) => {
-                    let resp = BoundResponse {
-                        bound_amount: bound.to_string(),
-                        unbound_amount: unbound.to_string(),
-                    };
-                    let data = serde_json::to_string(&resp).unwrap();
-                    set_to_redis(&mut conn, &key, &data)?;
-                    Ok(Json(resp))
-                }
-                Err(e) => return Err(IndexerError::IndexerCustom(e.to_string())),
+    let validator = params.validator.h160();
+    let delegator = params.delegator.h160();
+    let key = bound_key(&params.validator, &params.delegator);
+    let cache = state.cache.clone();
+    let ttl = state.cache.ttl.bound;
+    let res = cache
+        .get_or_fetch(&key, ttl, move || async move {
+            // The latest snapshot is current if no delegation of the pair happened after it.
+            let sql_position = r#"SELECT bound,unbound FROM evm_delegator_positions
+                WHERE validator=$1 AND delegator=$2
+                AND (SELECT max(block_num) FROM evm_delegator_position_runs) >=
+                    coalesce((SELECT max(block_num) FROM evm_audit WHERE validator=$1 AND delegator=$2),0)
+                ORDER BY block_num DESC LIMIT 1"#;
+            let row = sqlx::query(sql_position)
+                .bind(&params.validator)
+                .bind(&params.delegator)
+                .fetch_optional(&state.pool)
+                .await?;
+            if let Some(r) = row {
+                let bound: BigDecimal = r.try_get("bound")?;
+                let unbound: BigDecimal = r.try_get("unbound")?;
+                return Ok(BoundResponse {
+                    bound_amount: bound.to_string(),
+                    unbound_amount: unbound.to_string(),
+                });
             }
-        }
-    }
+
+            let (bound, unbound) = state.staking.delegators(validator, delegator).call().await?;
+            Ok(BoundResponse {
+                bound_amount: bound.to_string(),
+                unbound_amount: unbound.to_string(),
+            })
+        })
+        .await?;
+
+    Ok(Json(res))
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct DelegatorRewardParams {
-    pub address: String,
+    pub address: Address,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/reward",
+    tag = "contract",
+    params(DelegatorRewardParams),
+    responses(
+        (status = 200, body = RewardResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 pub async fn get_delegator_reward(
     State(state): State<Arc<AppState>>,
     params: Query<DelegatorRewardParams>,
 ) -> Result<Json<RewardResponse>> {
-    let reward = state.reward.clone();
-    let delegator = H160::from_str(&params.0.address)?;
-    let mut conn = state.redis.clone().get_connection()?;
-    let key = format!("{}:{:?}", KEY_REWARD_PREFIX, delegator);
-    let r: RedisResult<String> = conn.get(&key);
-    match r {
-        Ok(data) => {
-            let resp: RewardResponse = serde_json::from_str(&data).unwrap();
-            Ok(Json(resp))
-        }
-        Err(_) => match reward.rewards(delegator).call().await {
-            Ok(amount) => {
-                let resp = RewardResponse {
-                    reward: amount.to_string(),
-                };
-                let data = serde_json::to_string(&resp).unwrap();
-                set_to_redis(&mut conn, &key, &data)?;
-                Ok(Json(resp))
-            }
-            Err(e) => return Err(IndexerError::IndexerCustom(e.to_string())),
-        },
-    }
-}
+    let delegator = params.address.h160();
+    let key = reward_key(&params.address);
+    let cache = state.cache.clone();
+    let ttl = state.cache.ttl.reward;
+    let res = cache
+        .get_or_fetch(&key, ttl, move || async move {
+            let amount = state.reward.rewards(delegator).call().await?;
+            Ok(RewardResponse {
+                reward: amount.to_string(),
+            })
+        })
+        .await?;
 
-fn set_to_redis(conn: &mut Connection, key: &str, value: &str) -> Result<()> {
-    let mut rng = rand::thread_rng();
-    let r = rng.gen_range(0..10);
-    let ms = 10 * 60 * 1000 + r * 1000; // 10 min + r min
-    let opts = SetOptions::default()
-        .get(true)
-        .with_expiration(SetExpiry::PX(ms));
-    conn.set_options(&key, value, opts)?;
-    Ok(())
+    Ok(Json(res))
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct DelegatorDebtParams {
-    pub validator: String,
-    pub delegator: String,
+    pub validator: Address,
+    pub delegator: Address,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/debt",
+    tag = "contract",
+    params(DelegatorDebtParams),
+    responses(
+        (status = 200, body = DebtResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 pub async fn get_delegator_debt(
     State(state): State<Arc<AppState>>,
     params: Query<DelegatorDebtParams>,
 ) -> Result<Json<DebtResponse>> {
-    let reward = state.reward.clone();
-    let validator = H160::from_str(&params.validator)?;
-    let delegator = H160::from_str(&params.delegator)?;
-    match reward.reward_debt(validator, delegator).call().await {
-        Ok(amount) => Ok(Json(DebtResponse {
-            debt: amount.to_string(),
-        })),
-        Err(e) => Err(IndexerError::IndexerCustom(e.to_string())),
-    }
-}
-
-#[derive(Serialize, Deserialize)]
+    let validator = params.validator.h160();
+    let delegator = params.delegator.h160();
+    let key = debt_key(&params.validator, &params.delegator);
+    let cache = state.cache.clone();
+    let ttl = state.cache.ttl.debt;
+    let res = cache
+        .get_or_fetch(&key, ttl, move || async move {
+            let amount = state
+                .reward
+                .reward_debt(validator, delegator)
+                .call()
+                .await?;
+            Ok(DebtResponse {
+                debt: amount.to_string(),
+            })
+        })
+        .await?;
+
+    Ok(Json(res))
+}
+
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct SumParams {
-    pub address: String,
+    pub address: Address,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/sum",
+    tag = "contract",
+    params(SumParams),
+    responses(
+        (status = 200, body = DelegatorSumResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 pub async fn get_delegator_sum(
     State(state): State<Arc<AppState>>,
     params: Query<SumParams>,
 ) -> Result<Json<DelegatorSumResponse>> {
     let mut pool = state.pool.acquire().await?;
     let address = params.0.address;
-    let sql_query = format!(
-        "SELECT (SELECT sum(amount) FROM evm_delegations WHERE delegator='{}') as sd, \
-    (SELECT sum(amount) FROM evm_undelegations WHERE delegator='{}') as sund, \
-    (SELECT sum(amount) FROM evm_coinbase_mint WHERE delegator='{}') as sc",
-        address, address, address
-    );
-    let row = sqlx::query(&sql_query).fetch_one(&mut *pool).await?;
+    let sql_query = "SELECT (SELECT sum(amount) FROM evm_delegations WHERE delegator=$1) as sd, \
+    (SELECT sum(amount) FROM evm_undelegations WHERE delegator=$1) as sund, \
+    (SELECT sum(amount) FROM evm_coinbase_mint WHERE delegator=$1) as sc";
+    let row = sqlx::query(sql_query)
+        .bind(&address)
+        .fetch_one(&mut *pool)
+        .await?;
     let sum_delegate: BigDecimal = row.try_get("sd").unwrap_or_default();
     let sum_undelegate: BigDecimal = row.try_get("sund").unwrap_or_default();
     let sum_claim: BigDecimal = row.try_get("sc").unwrap_or_default();
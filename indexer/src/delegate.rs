@@ -1,133 +1,250 @@
 use crate::error::Result;
-use crate::types::{DelegateResponse, QueryResult};
+use crate::params::{Address, Query};
+use crate::query::{fetch_records, ListQuery, Page, RecordFilter, Total, RECORD_KEY};
+use crate::types::{DelegateResponse, ErrorResponse, ListResult};
 use crate::AppState;
-use axum::extract::{Query, State};
+use axum::extract::State;
 use axum::Json;
 use serde::{Deserialize, Serialize};
 use sqlx::types::chrono::NaiveDateTime;
 use sqlx::types::BigDecimal;
 use sqlx::Row;
 use std::sync::Arc;
+use utoipa::IntoParams;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct DelegatorDelegateRecordsParams {
-    pub delegator: Option<String>,
+    pub delegator: Option<Address>,
+    pub validator: Option<Address>,
     pub page: Option<i32>,
     pub page_size: Option<i32>,
+    pub cursor: Option<String>,
+    pub total: Option<Total>,
 }
 
+fn delegator_delegate_query(params: &DelegatorDelegateRecordsParams) -> ListQuery {
+    ListQuery::new("FROM evm_delegations")
+        .eq("delegator", params.delegator.clone())
+        .eq("validator", params.validator.clone())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/records/delegate/delegator",
+    tag = "delegate",
+    params(DelegatorDelegateRecordsParams, RecordFilter),
+    responses(
+        (status = 200, body = ListResult<Vec<DelegateResponse>>),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 pub async fn get_delegator_delegate_records(
     State(state): State<Arc<AppState>>,
     params: Query<DelegatorDelegateRecordsParams>,
-) -> Result<Json<QueryResult<Vec<DelegateResponse>>>> {
+    filter: Query<RecordFilter>,
+) -> Result<Json<ListResult<Vec<DelegateResponse>>>> {
     let mut pool = state.pool.acquire().await?;
-    let page = params.page.unwrap_or(1);
-    let page_size = params.page_size.unwrap_or(10);
-
-    let (sql_total, sql_query) = if let Some(delegator) = params.0.delegator {
-        let delegator_lower = delegator.to_lowercase();
-        (
-            format!("SELECT count(block_num) FROM evm_delegations WHERE delegator='{}'", delegator_lower)
-            ,
-            format!("SELECT tx_id,block_id,block_num,validator,delegator,tm,amount FROM evm_delegations WHERE delegator='{}' ORDER BY tm DESC LIMIT {} OFFSET {}", delegator_lower, page_size, (page-1)*page_size)
-        )
-    } else {
-        (
-            "SELECT count(block_num) FROM evm_delegations".to_string()
-            ,
-            format!("SELECT tx_id,block_id,block_num,validator,delegator,tm,amount FROM evm_delegations ORDER BY tm DESC LIMIT {} OFFSET {}", page_size, (page-1)*page_size))
-    };
-
-    let row = sqlx::query(&sql_total).fetch_one(&mut *pool).await?;
-    let total: i64 = row.try_get("count")?;
-
-    let mut delegates: Vec<DelegateResponse> = vec![];
-    let rows = sqlx::query(&sql_query).fetch_all(&mut *pool).await?;
-    for r in rows {
-        let tx_hash: String = r.try_get("tx_id")?;
-        let block_hash: String = r.try_get("block_id")?;
-        let block_num: i64 = r.try_get("block_num")?;
-        let validator: String = r.try_get("validator")?;
-        let delegator: String = r.try_get("delegator")?;
-        let amount: BigDecimal = r.try_get("amount")?;
-        let tm: NaiveDateTime = r.try_get("tm")?;
-
-        delegates.push(DelegateResponse {
-            tx_hash,
-            block_hash,
-            block_num,
-            validator,
-            delegator,
-            amount: amount.to_string(),
-            timestamp: tm.and_utc().timestamp(),
-        })
-    }
+    let page = Page::new(
+        params.page,
+        params.page_size,
+        params.cursor.as_deref(),
+        params.total,
+    )?;
 
-    Ok(Json(QueryResult {
-        total,
+    let query = delegator_delegate_query(&params).records(&filter, Some("amount"))?;
+
+    let res = fetch_records(
+        &mut pool,
+        &query,
+        "tx_id,block_id,block_num,validator,delegator,tm,amount,log_index",
+        RECORD_KEY,
         page,
-        page_size,
-        data: delegates,
-    }))
+        |r| {
+            let tx_hash: String = r.try_get("tx_id")?;
+            let block_hash: String = r.try_get("block_id")?;
+            let block_num: i64 = r.try_get("block_num")?;
+            let validator: String = r.try_get("validator")?;
+            let delegator: String = r.try_get("delegator")?;
+            let amount: BigDecimal = r.try_get("amount")?;
+            let tm: NaiveDateTime = r.try_get("tm")?;
+
+            Ok(DelegateResponse {
+                tx_hash,
+                block_hash,
+                block_num,
+                validator,
+                delegator,
+                amount: amount.to_string(),
+                timestamp: tm.and_utc().timestamp(),
+            })
+        },
+    )
+    .await?;
+
+    Ok(Json(res))
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ValidatorDelegateRecordsParams {
-    pub validator: Option<String>,
+    pub validator: Option<Address>,
+    pub delegator: Option<Address>,
     pub page: Option<i32>,
     pub page_size: Option<i32>,
+    pub cursor: Option<String>,
+    pub total: Option<Total>,
 }
 
+fn validator_delegate_query(params: &ValidatorDelegateRecordsParams) -> ListQuery {
+    ListQuery::new("FROM evm_delegations")
+        .eq("validator", params.validator.clone())
+        .eq("delegator", params.delegator.clone())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/records/delegate",
+    tag = "delegate",
+    params(ValidatorDelegateRecordsParams, RecordFilter),
+    responses(
+        (status = 200, body = ListResult<Vec<DelegateResponse>>),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 pub async fn get_validator_delegate_records(
     State(state): State<Arc<AppState>>,
     params: Query<ValidatorDelegateRecordsParams>,
-) -> Result<Json<QueryResult<Vec<DelegateResponse>>>> {
+    filter: Query<RecordFilter>,
+) -> Result<Json<ListResult<Vec<DelegateResponse>>>> {
     let mut pool = state.pool.acquire().await?;
-    let page = params.page.unwrap_or(1);
-    let page_size = params.page_size.unwrap_or(10);
-
-    let (sql_total, sql_query) = if let Some(validator) = params.0.validator {
-        (
-            format!("SELECT count(block_num) FROM evm_delegations WHERE validator='{}'", validator)
-            ,
-            format!("SELECT tx_id,block_id,block_num,validator,delegator,tm,amount FROM evm_delegations WHERE validator='{}' ORDER BY tm DESC LIMIT {} OFFSET {}", validator, page_size, (page-1)*page_size)
-        )
-    } else {
-        (
-            "SELECT count(block_num) FROM evm_delegations".to_string()
-            ,
-            format!("SELECT tx_id,block_id,block_num,validator,delegator,tm,amount FROM evm_delegations ORDER BY tm DESC LIMIT {} OFFSET {}", page_size, (page-1)*page_size))
-    };
-
-    let row = sqlx::query(&sql_total).fetch_one(&mut *pool).await?;
-    let total: i64 = row.try_get("count")?;
-
-    let mut delegates: Vec<DelegateResponse> = vec![];
-    let rows = sqlx::query(&sql_query).fetch_all(&mut *pool).await?;
-    for r in rows {
-        let tx_hash: String = r.try_get("tx_id")?;
-        let block_hash: String = r.try_get("block_id")?;
-        let block_num: i64 = r.try_get("block_num")?;
-        let validator: String = r.try_get("validator")?;
-        let delegator: String = r.try_get("delegator")?;
-        let amount: BigDecimal = r.try_get("amount")?;
-        let tm: NaiveDateTime = r.try_get("tm")?;
-
-        delegates.push(DelegateResponse {
-            tx_hash,
-            block_hash,
-            block_num,
-            validator,
-            delegator,
-            amount: amount.to_string(),
-            timestamp: tm.and_utc().timestamp(),
-        })
-    }
+    let page = Page::new(
+        params.page,
+        params.page_size,
+        params.cursor.as_deref(),
+        params.total,
+    )?;
+
+    let query = validator_delegate_query(&params).records(&filter, Some("amount"))?;
 
-    Ok(Json(QueryResult {
-        total,
+    let res = fetch_records(
+        &mut pool,
+        &query,
+        "tx_id,block_id,block_num,validator,delegator,tm,amount,log_index",
+        RECORD_KEY,
         page,
-        page_size,
-        data: delegates,
-    }))
+        |r| {
+            let tx_hash: String = r.try_get("tx_id")?;
+            let block_hash: String = r.try_get("block_id")?;
+            let block_num: i64 = r.try_get("block_num")?;
+            let validator: String = r.try_get("validator")?;
+            let delegator: String = r.try_get("delegator")?;
+            let amount: BigDecimal = r.try_get("amount")?;
+            let tm: NaiveDateTime = r.try_get("tm")?;
+
+            Ok(DelegateResponse {
+                tx_hash,
+                block_hash,
+                block_num,
+                validator,
+                delegator,
+                amount: amount.to_string(),
+                timestamp: tm.and_utc().timestamp(),
+            })
+        },
+    )
+    .await?;
+
+    Ok(Json(res))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::tests::assert_filters;
+    use crate::query::Param;
+
+    const FROM: &str = "FROM evm_delegations";
+    const ADDR: &str = "0x00000000000000000000000000000000000000ab";
+    const OTHER: &str = "0x00000000000000000000000000000000000000cd";
+
+    #[test]
+    fn filter_delegator() {
+        let query = delegator_delegate_query(&DelegatorDelegateRecordsParams {
+            delegator: Some(ADDR.parse().unwrap()),
+            ..Default::default()
+        });
+        assert_filters(
+            &query,
+            FROM,
+            &[("delegator", "=", Param::Text(ADDR.into()))],
+        );
+    }
+
+    #[test]
+    fn filter_validator() {
+        let query = validator_delegate_query(&ValidatorDelegateRecordsParams {
+            validator: Some(ADDR.parse().unwrap()),
+            ..Default::default()
+        });
+        assert_filters(
+            &query,
+            FROM,
+            &[("validator", "=", Param::Text(ADDR.into()))],
+        );
+    }
+
+    #[test]
+    fn filter_pair() {
+        let query = delegator_delegate_query(&DelegatorDelegateRecordsParams {
+            delegator: Some(ADDR.parse().unwrap()),
+            validator: Some(OTHER.parse().unwrap()),
+            ..Default::default()
+        });
+        assert_filters(
+            &query,
+            FROM,
+            &[
+                ("delegator", "=", Param::Text(ADDR.into())),
+                ("validator", "=", Param::Text(OTHER.into())),
+            ],
+        );
+        let query = validator_delegate_query(&ValidatorDelegateRecordsParams {
+            validator: Some(OTHER.parse().unwrap()),
+            delegator: Some(ADDR.parse().unwrap()),
+            ..Default::default()
+        });
+        assert_filters(
+            &query,
+            FROM,
+            &[
+                ("validator", "=", Param::Text(OTHER.into())),
+                ("delegator", "=", Param::Text(ADDR.into())),
+            ],
+        );
+    }
+
+    #[test]
+    fn filter_block() {
+        let filter = RecordFilter {
+            from_block: Some(5),
+            to_block: Some(9),
+            ..Default::default()
+        };
+        let query = delegator_delegate_query(&DelegatorDelegateRecordsParams {
+            delegator: Some(ADDR.parse().unwrap()),
+            ..Default::default()
+        })
+        .records(&filter, Some("amount"))
+        .unwrap();
+        assert_filters(
+            &query,
+            FROM,
+            &[
+                ("delegator", "=", Param::Text(ADDR.into())),
+                ("block_num", ">=", Param::Int(5)),
+                ("block_num", "<=", Param::Int(9)),
+            ],
+        );
+    }
 }
@@ -1,8 +1,15 @@
+use crate::request_id;
+use crate::types::ErrorResponse;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use sqlx::Error::RowNotFound;
+use axum::Json;
+use ethers::contract::ContractError;
+use ethers::prelude::{Http, Provider};
+use log::error;
+use std::fmt;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum IndexerError {
     IndexerCustom(String),
     IndexerDBError(sqlx::Error),
@@ -11,6 +18,15 @@ pub enum IndexerError {
     IndexerHexError(rustc_hex::FromHexError),
     IndexerParseUrlError(url::ParseError),
     IndexerRedisError(redis::RedisError),
+    IndexerContractError(ContractError<Provider<Http>>),
+    /// Rejected query parameter and the reason
+    IndexerInvalidParam(String, String),
+}
+
+impl From<ContractError<Provider<Http>>> for IndexerError {
+    fn from(e: ContractError<Provider<Http>>) -> Self {
+        IndexerError::IndexerContractError(e)
+    }
 }
 
 impl From<redis::RedisError> for IndexerError {
@@ -55,23 +71,143 @@ impl From<sqlx::Error> for IndexerError {
     }
 }
 
+impl fmt::Display for IndexerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexerError::IndexerCustom(e) => write!(f, "{}", e),
+            IndexerError::IndexerDBError(e) => write!(f, "db: {}", e),
+            IndexerError::IndexerIOError(e) => write!(f, "io: {}", e),
+            IndexerError::IndexerTomlDeError(e) => write!(f, "toml: {}", e),
+            IndexerError::IndexerHexError(e) => write!(f, "hex: {}", e),
+            IndexerError::IndexerParseUrlError(e) => write!(f, "url: {}", e),
+            IndexerError::IndexerRedisError(e) => write!(f, "redis: {}", e),
+            IndexerError::IndexerContractError(e) => write!(f, "contract: {}", e),
+            IndexerError::IndexerInvalidParam(param, reason) => {
+                write!(f, "invalid parameter `{}`: {}", param, reason)
+            }
+        }
+    }
+}
+
 pub type Result<T> = core::result::Result<T, IndexerError>;
 
+impl IndexerError {
+    /// Status, error code and client-facing message. Details of server side failures are
+    /// only logged.
+    fn classify(&self) -> (StatusCode, &'static str, String) {
+        match self {
+            IndexerError::IndexerInvalidParam(param, reason) if param == "." => {
+                (StatusCode::BAD_REQUEST, "invalid_param", reason.clone())
+            }
+            IndexerError::IndexerInvalidParam(param, reason) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_param",
+                format!("invalid parameter `{}`: {}", param, reason),
+            ),
+            IndexerError::IndexerHexError(e) => {
+                (StatusCode::BAD_REQUEST, "invalid_param", e.to_string())
+            }
+            IndexerError::IndexerDBError(sqlx::Error::RowNotFound) => {
+                (StatusCode::NOT_FOUND, "not_found", "not found".into())
+            }
+            IndexerError::IndexerDBError(
+                sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed | sqlx::Error::Io(_),
+            ) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "db_unavailable",
+                "database unavailable".into(),
+            ),
+            IndexerError::IndexerRedisError(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "cache_unavailable",
+                "cache unavailable".into(),
+            ),
+            IndexerError::IndexerContractError(e) => match e.decode_revert::<String>() {
+                Some(reason) => (
+                    StatusCode::BAD_REQUEST,
+                    "contract_revert",
+                    format!("contract call reverted: {}", reason),
+                ),
+                None if e.is_revert() => (
+                    StatusCode::BAD_REQUEST,
+                    "contract_revert",
+                    "contract call reverted".into(),
+                ),
+                None if is_rate_limited(&e.to_string()) => (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    "rpc_rate_limited",
+                    "rpc node rate limit exceeded".into(),
+                ),
+                None => (
+                    StatusCode::BAD_GATEWAY,
+                    "rpc_error",
+                    "rpc node request failed".into(),
+                ),
+            },
+            _ => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal",
+                "internal error".into(),
+            ),
+        }
+    }
+}
+
+fn is_rate_limited(msg: &str) -> bool {
+    let msg = msg.to_lowercase();
+    msg.contains("429") || msg.contains("too many requests") || msg.contains("rate limit")
+}
+
 impl IntoResponse for IndexerError {
     fn into_response(self) -> Response {
-        let err_msg = match self {
-            IndexerError::IndexerCustom(e) => e,
-            IndexerError::IndexerDBError(e) => match e {
-                RowNotFound => return (StatusCode::NOT_FOUND, "not found").into_response(),
-                _ => e.to_string(),
-            },
-            IndexerError::IndexerIOError(e) => e.to_string(),
-            IndexerError::IndexerTomlDeError(e) => e.to_string(),
-            IndexerError::IndexerHexError(e) => e.to_string(),
-            IndexerError::IndexerParseUrlError(e) => e.to_string(),
-            IndexerError::IndexerRedisError(e) => e.to_string(),
+        let (status, code, message) = self.classify();
+        let request_id = request_id::current();
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            error!("[{}] {}: {}", request_id, code, self);
+        }
+
+        let body = ErrorResponse {
+            code: code.to_string(),
+            message,
+            request_id,
         };
+        (status, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        (StatusCode::INTERNAL_SERVER_ERROR, err_msg).into_response()
+    #[test]
+    fn status_by_source() {
+        let cases = [
+            (
+                IndexerError::IndexerInvalidParam("validator".into(), "bad".into()),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                IndexerError::IndexerDBError(sqlx::Error::RowNotFound),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                IndexerError::IndexerDBError(sqlx::Error::PoolTimedOut),
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
+            (
+                IndexerError::IndexerDBError(sqlx::Error::ColumnNotFound("secret".into())),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                IndexerError::IndexerContractError(ContractError::Revert(Default::default())),
+                StatusCode::BAD_REQUEST,
+            ),
+        ];
+        for (e, status) in cases {
+            let (s, _, message) = e.classify();
+            assert_eq!(s, status);
+            assert!(!message.contains("secret"));
+        }
+        assert!(is_rate_limited("HTTP error 429 Too Many Requests"));
     }
 }
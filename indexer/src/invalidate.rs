@@ -0,0 +1,86 @@
+use crate::contract::delegator_keys;
+use crate::error::Result;
+use crate::params::Address;
+use crate::AppState;
+use log::{error, info};
+use serde::Deserialize;
+use sqlx::postgres::PgListener;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Channel the scanner notifies with the validator/delegator pairs touched by the events of a
+/// block. Must match the scanner's.
+const DELEGATOR_CHANNEL: &str = "evm_delegator_changed";
+const LISTEN_RETRY_DELAY: u64 = 5; // 5s
+
+#[derive(Deserialize)]
+struct PairNotification {
+    block_num: i64,
+    pairs: Vec<(String, String)>,
+}
+
+fn notified_keys(payload: &str) -> Vec<String> {
+    let notification = match serde_json::from_str::<PairNotification>(payload) {
+        Ok(n) => n,
+        Err(e) => {
+            error!("Invalid notification {}: {:?}", payload, e);
+            return vec![];
+        }
+    };
+    let mut keys = BTreeSet::new();
+    for (validator, delegator) in notification.pairs {
+        match (validator.parse::<Address>(), delegator.parse::<Address>()) {
+            (Ok(v), Ok(d)) => keys.extend(delegator_keys(&v, &d)),
+            _ => error!(
+                "Invalid pair {}/{} notified at block {}",
+                validator, delegator, notification.block_num
+            ),
+        }
+    }
+    keys.into_iter().collect()
+}
+
+/// Evicts the cached bound, reward and debt of the pairs the scanner notifies right after it
+/// indexed their Delegation, Undelegation or CoinbaseMint events.
+pub async fn run(state: Arc<AppState>) {
+    loop {
+        if let Err(e) = listen(&state).await {
+            error!("Listen on {} error: {:?}", DELEGATOR_CHANNEL, e);
+        }
+
+        tokio::time::sleep(Duration::from_secs(LISTEN_RETRY_DELAY)).await
+    }
+}
+
+async fn listen(state: &AppState) -> Result<()> {
+    let mut listener = PgListener::connect_with(&state.pool).await?;
+    listener.listen(DELEGATOR_CHANNEL).await?;
+    info!("Listening on {}", DELEGATOR_CHANNEL);
+    loop {
+        let notification = listener.recv().await?;
+        let keys = notified_keys(notification.payload());
+        state.cache.evict(&keys).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_of_notified_pairs() {
+        let payload = r#"{"block_num":7,"pairs":[
+            ["0x00000000000000000000000000000000000000AB","0x00000000000000000000000000000000000000cd"],
+            ["0x00000000000000000000000000000000000000ab","bad"]]}"#;
+        assert_eq!(
+            notified_keys(payload),
+            vec![
+                "E:BND:0x00000000000000000000000000000000000000cd:0x00000000000000000000000000000000000000ab",
+                "E:DBT:0x00000000000000000000000000000000000000cd:0x00000000000000000000000000000000000000ab",
+                "E:RWD:0x00000000000000000000000000000000000000cd",
+            ]
+        );
+        assert!(notified_keys("{}").is_empty());
+    }
+}
@@ -1,28 +1,44 @@
 mod delegate;
 mod error;
 mod receipt;
+mod reward;
 mod stake;
+mod stats;
 
+mod cache;
 mod contract;
+mod invalidate;
 mod mint;
+mod openapi;
+mod params;
+mod position;
+mod query;
+mod request_id;
 mod types;
 mod undelegate;
 mod validators;
 
+use crate::cache::{Cache, CacheConfig};
 use crate::contract::{
     get_delegator_bound, get_delegator_debt, get_delegator_reward, get_delegator_sum,
     get_validator_data, get_validator_status,
 };
 use crate::delegate::{get_delegator_delegate_records, get_validator_delegate_records};
 use crate::mint::{get_delegator_mint_records, get_validator_mint_records};
+use crate::position::{get_position_history, get_top_delegators};
 use crate::receipt::get_receipts;
+use crate::reward::{get_reward_split, get_reward_split_history};
 use crate::stake::get_stake_records;
-use crate::undelegate::{get_delegator_undelegate_records, get_validator_undelegate_records};
+use crate::stats::get_network_stats;
+use crate::undelegate::{
+    get_delegator_undelegate_records, get_pending_undelegations, get_validator_undelegate_records,
+};
 use crate::validators::{
-    get_delegators_of_validator, get_latest20, get_validator_sum_reward, get_validator_votes,
-    get_validators, get_validators_of_delegator,
+    get_delegators_of_validator, get_latest20, get_validator_history, get_validator_sum_reward,
+    get_validator_timeline, get_validator_votes, get_validators, get_validators_of_delegator,
 };
 use axum::http::Method;
+use axum::middleware;
 use axum::routing::get;
 use axum::Router;
 use error::Result;
@@ -37,6 +53,8 @@ use std::fs::File;
 use std::io::Read;
 use std::sync::Arc;
 use tower_http::cors::{Any, CorsLayer};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
 
 abigen!(RewardContract, "../abi/Reward.json");
 abigen!(StakingContract, "../abi/Staking.json");
@@ -48,7 +66,10 @@ struct IndexerConfig {
     pub reward: String,
     pub listen: String,
     pub db_url: String,
-    pub redis_url: String,
+    /// Shared cache of several instances, an in-process cache is used without it
+    pub redis_url: Option<String>,
+    #[serde(default)]
+    pub cache: CacheConfig,
 }
 
 impl IndexerConfig {
@@ -63,7 +84,7 @@ impl IndexerConfig {
 
 struct AppState {
     pub pool: PgPool,
-    pub redis: redis::Client,
+    pub cache: Arc<Cache>,
     pub staking: StakingContract<Provider<Http>>,
     pub reward: RewardContract<Provider<Http>>,
 }
@@ -89,11 +110,12 @@ async fn main() -> Result<()> {
         .await
         .expect("can't connect to database");
     info!("Connecting db...ok");
+    schema::check_schema_version(&pool, &["scanner", "updater"]).await?;
 
-    let redis_client = redis::Client::open(config.redis_url)?;
+    let cache = Arc::new(Cache::new(config.redis_url.as_deref(), config.cache)?);
     let app_state = Arc::new(AppState {
         pool,
-        redis: redis_client,
+        cache,
         staking,
         reward,
     });
@@ -108,6 +130,8 @@ async fn main() -> Result<()> {
             "/api/validator/delegators",
             get(get_delegators_of_validator),
         )
+        .route("/api/validator/history", get(get_validator_history))
+        .route("/api/validator/timeline", get(get_validator_timeline))
         .route(
             "/api/delegator/validators",
             get(get_validators_of_delegator),
@@ -135,17 +159,27 @@ async fn main() -> Result<()> {
             get(get_validator_mint_records),
         )
         .route("/api/diff/vote", get(get_validator_votes))
+        .route("/api/undelegations/pending", get(get_pending_undelegations))
         .route("/api/records/stake", get(get_stake_records))
         .route("/api/receipts", get(get_receipts))
         .route("/api/bound", get(get_delegator_bound))
+        .route("/api/bound/history", get(get_position_history))
+        .route("/api/validator/top_delegators", get(get_top_delegators))
         .route("/api/reward", get(get_delegator_reward))
         .route("/api/debt", get(get_delegator_debt))
         .route("/api/sum", get(get_delegator_sum))
         .route("/api/vdata", get(get_validator_data))
         .route("/api/vstatus", get(get_validator_status))
         .route("/api/reward/sum", get(get_validator_sum_reward))
+        .route("/api/reward/split", get(get_reward_split))
+        .route("/api/reward/split/history", get(get_reward_split_history))
+        .route("/api/stats/network", get(get_network_stats))
+        .merge(SwaggerUi::new("/api/docs").url("/api/openapi.json", openapi::ApiDoc::openapi()))
+        .layer(middleware::from_fn(request_id::request_id))
         .layer(cors)
-        .with_state(app_state);
+        .with_state(app_state.clone());
+
+    tokio::spawn(invalidate::run(app_state));
 
     let listener = tokio::net::TcpListener::bind(config.listen).await.unwrap();
 
@@ -0,0 +1,65 @@
+use crate::{
+    contract, delegate, mint, position, receipt, reward, stake, stats, undelegate, validators,
+};
+use utoipa::OpenApi;
+
+/// OpenAPI document of every route, served at `/api/openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Evm Staking Indexer API"),
+    paths(
+        validators::get_validators,
+        validators::get_delegators_of_validator,
+        validators::get_validator_history,
+        validators::get_validator_timeline,
+        validators::get_validators_of_delegator,
+        validators::get_latest20,
+        validators::get_validator_votes,
+        validators::get_validator_sum_reward,
+        delegate::get_delegator_delegate_records,
+        delegate::get_validator_delegate_records,
+        undelegate::get_delegator_undelegate_records,
+        undelegate::get_validator_undelegate_records,
+        undelegate::get_pending_undelegations,
+        mint::get_delegator_mint_records,
+        mint::get_validator_mint_records,
+        stake::get_stake_records,
+        receipt::get_receipts,
+        position::get_position_history,
+        position::get_top_delegators,
+        contract::get_delegator_bound,
+        contract::get_delegator_reward,
+        contract::get_delegator_debt,
+        contract::get_delegator_sum,
+        contract::get_validator_data,
+        contract::get_validator_status,
+        reward::get_reward_split,
+        reward::get_reward_split_history,
+        stats::get_network_stats,
+    )
+)]
+pub struct ApiDoc;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents_every_route() {
+        let spec = ApiDoc::openapi();
+        let main = include_str!("main.rs");
+        let routes = main
+            .split(".route(")
+            .skip(1)
+            .filter_map(|r| r.split('"').nth(1))
+            .collect::<Vec<&str>>();
+        assert!(!routes.is_empty());
+        for route in routes {
+            assert!(
+                spec.paths.paths.contains_key(route),
+                "{} is undocumented",
+                route
+            );
+        }
+    }
+}
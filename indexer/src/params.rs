@@ -0,0 +1,220 @@
+use crate::error::IndexerError;
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use ethers::types::H160;
+use serde::de::{DeserializeOwned, Error};
+use serde::{Deserialize, Deserializer, Serialize};
+use sqlx::types::BigDecimal;
+use std::ops::Deref;
+use std::str::FromStr;
+use utoipa::openapi::schema::{ObjectBuilder, Schema, Type};
+use utoipa::openapi::RefOr;
+use utoipa::{PartialSchema, ToSchema};
+
+/// Normalizes `0x`-prefixed or bare hex of `len` bytes, in any case, to the stored form:
+/// `0x` followed by lowercase hex.
+fn normalize_hex(s: &str, len: usize) -> Result<String, String> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    if digits.len() != len * 2 {
+        return Err(format!(
+            "expected {} hex digits, got {}",
+            len * 2,
+            digits.len()
+        ));
+    }
+    if let Some(c) = digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(format!("invalid hex character '{}'", c));
+    }
+    Ok(format!("0x{}", digits.to_ascii_lowercase()))
+}
+
+/// EVM address as stored by the scanner. Checksummed or any-case input is accepted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct Address(String);
+
+impl Address {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn h160(&self) -> H160 {
+        H160::from_str(&self.0).expect("validated address")
+    }
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        normalize_hex(s, 20).map(Address)
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Address::from_str(&s).map_err(D::Error::custom)
+    }
+}
+
+/// 32-byte transaction or block hash as stored by the scanner.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct Hash(String);
+
+impl Hash {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for Hash {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        normalize_hex(s, 32).map(Hash)
+    }
+}
+
+impl<'de> Deserialize<'de> for Hash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Hash::from_str(&s).map_err(D::Error::custom)
+    }
+}
+
+/// Non-negative token amount in wei.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Amount(pub BigDecimal);
+
+impl FromStr for Amount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match BigDecimal::from_str(s) {
+            Ok(v) if v >= BigDecimal::from(0) => Ok(Amount(v)),
+            Ok(_) => Err("must not be negative".to_string()),
+            Err(_) => Err(format!("invalid amount '{}'", s)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Amount::from_str(&s).map_err(D::Error::custom)
+    }
+}
+
+fn string_schema(pattern: &str, description: &str) -> RefOr<Schema> {
+    ObjectBuilder::new()
+        .schema_type(Type::String)
+        .pattern(Some(pattern))
+        .description(Some(description))
+        .into()
+}
+
+impl PartialSchema for Address {
+    fn schema() -> RefOr<Schema> {
+        string_schema(
+            "^(0[xX])?[0-9a-fA-F]{40}$",
+            "EVM address, checksummed or any case",
+        )
+    }
+}
+
+impl ToSchema for Address {}
+
+impl PartialSchema for Hash {
+    fn schema() -> RefOr<Schema> {
+        string_schema("^(0[xX])?[0-9a-fA-F]{64}$", "32-byte hash, any case")
+    }
+}
+
+impl ToSchema for Hash {}
+
+impl PartialSchema for Amount {
+    fn schema() -> RefOr<Schema> {
+        string_schema("^[0-9]+(\\.[0-9]+)?$", "Amount in wei")
+    }
+}
+
+impl ToSchema for Amount {}
+
+/// Query string extractor, rejects bad input with a 400 naming the offending parameter.
+#[derive(Debug)]
+pub struct Query<T>(pub T);
+
+impl<T> Deref for Query<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+fn parse_query<T: DeserializeOwned>(query: &str) -> Result<T, IndexerError> {
+    let de = serde_urlencoded::Deserializer::new(form_urlencoded::parse(query.as_bytes()));
+    serde_path_to_error::deserialize(de).map_err(|e| {
+        let param = e.path().to_string();
+        IndexerError::IndexerInvalidParam(param, e.into_inner().to_string())
+    })
+}
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = IndexerError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parse_query(parts.uri.query().unwrap_or_default()).map(Query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDR: &str = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+
+    #[test]
+    fn address_any_case() {
+        let checksum = Address::from_str("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").unwrap();
+        let upper = Address::from_str("0X5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED").unwrap();
+        let bare = Address::from_str(&ADDR[2..]).unwrap();
+        assert_eq!(checksum.as_str(), ADDR);
+        assert_eq!(upper.as_str(), ADDR);
+        assert_eq!(bare.as_str(), ADDR);
+    }
+
+    #[test]
+    fn address_rejects_bad_input() {
+        assert!(Address::from_str(&ADDR[..40]).is_err());
+        assert!(Address::from_str(&ADDR.replace('a', "g")).is_err());
+        assert!(Hash::from_str(ADDR).is_err());
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Params {
+        delegator: Address,
+        txid: Option<Hash>,
+    }
+
+    #[test]
+    fn query_names_bad_param() {
+        let err = parse_query::<Params>(&format!("delegator={}&txid=0x12", ADDR)).unwrap_err();
+        match err {
+            IndexerError::IndexerInvalidParam(param, _) => assert_eq!(param, "txid"),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use crate::error::Result;
+use crate::params::{Address, Query};
+use crate::query::{offset_page, rows_before};
+use crate::types::{DelegatorPositionResponse, ErrorResponse, QueryResult};
+use crate::AppState;
+use axum::extract::State;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgRow;
+use sqlx::types::chrono::NaiveDateTime;
+use sqlx::types::BigDecimal;
+use sqlx::Row;
+use std::sync::Arc;
+use utoipa::IntoParams;
+
+fn position_from_row(r: &PgRow) -> Result<DelegatorPositionResponse> {
+    let bound: BigDecimal = r.try_get("bound")?;
+    let unbound: BigDecimal = r.try_get("unbound")?;
+    let tm: NaiveDateTime = r.try_get("tm")?;
+    Ok(DelegatorPositionResponse {
+        validator: r.try_get("validator")?,
+        delegator: r.try_get("delegator")?,
+        block_num: r.try_get("block_num")?,
+        timestamp: tm.and_utc().timestamp(),
+        bound: bound.to_string(),
+        unbound: unbound.to_string(),
+    })
+}
+
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PositionHistoryParams {
+    pub validator: Address,
+    pub delegator: Address,
+    pub page: Option<i32>,
+    pub page_size: Option<i32>,
+}
+
+/// Snapshots of one delegator-validator pair, newest first. A row marks a change of the position.
+#[utoipa::path(
+    get,
+    path = "/api/bound/history",
+    tag = "position",
+    params(PositionHistoryParams),
+    responses(
+        (status = 200, body = QueryResult<Vec<DelegatorPositionResponse>>),
+        (status = 400, body = ErrorResponse),
+    )
+)]
+pub async fn get_position_history(
+    State(state): State<Arc<AppState>>,
+    params: Query<PositionHistoryParams>,
+) -> Result<Json<QueryResult<Vec<DelegatorPositionResponse>>>> {
+    let mut pool = state.pool.acquire().await?;
+    let (page, page_size) = offset_page(params.page, params.page_size)?;
+
+    let sql_total =
+        r#"SELECT count(*) FROM evm_delegator_positions WHERE validator=$1 AND delegator=$2"#;
+    let row = sqlx::query(sql_total)
+        .bind(&params.0.validator)
+        .bind(&params.0.delegator)
+        .fetch_one(&mut *pool)
+        .await?;
+    let total: i64 = row.try_get("count")?;
+
+    let sql_query = r#"SELECT block_num,tm,validator,delegator,bound,unbound FROM evm_delegator_positions
+        WHERE validator=$1 AND delegator=$2 ORDER BY block_num DESC LIMIT $3 OFFSET $4"#;
+    let rows = sqlx::query(sql_query)
+        .bind(&params.0.validator)
+        .bind(&params.0.delegator)
+        .bind(page_size)
+        .bind(rows_before(page, page_size))
+        .fetch_all(&mut *pool)
+        .await?;
+    let mut positions: Vec<DelegatorPositionResponse> = vec![];
+    for r in rows {
+        positions.push(position_from_row(&r)?);
+    }
+
+    Ok(Json(QueryResult {
+        total,
+        page,
+        page_size,
+        data: positions,
+    }))
+}
+
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TopDelegatorsParams {
+    pub validator: Address,
+    pub page: Option<i32>,
+    pub page_size: Option<i32>,
+}
+
+/// Delegators of a validator ordered by their latest snapshotted bound amount.
+#[utoipa::path(
+    get,
+    path = "/api/validator/top_delegators",
+    tag = "position",
+    params(TopDelegatorsParams),
+    responses(
+        (status = 200, body = QueryResult<Vec<DelegatorPositionResponse>>),
+        (status = 400, body = ErrorResponse),
+    )
+)]
+pub async fn get_top_delegators(
+    State(state): State<Arc<AppState>>,
+    params: Query<TopDelegatorsParams>,
+) -> Result<Json<QueryResult<Vec<DelegatorPositionResponse>>>> {
+    let mut pool = state.pool.acquire().await?;
+    let (page, page_size) = offset_page(params.page, params.page_size)?;
+
+    let sql_latest = r#"SELECT DISTINCT ON (delegator) block_num,tm,validator,delegator,bound,unbound
+        FROM evm_delegator_positions WHERE validator=$1 ORDER BY delegator,block_num DESC"#;
+
+    let sql_total = format!("SELECT count(*) FROM ({}) p WHERE bound>0", sql_latest);
+    let row = sqlx::query(&sql_total)
+        .bind(&params.0.validator)
+        .fetch_one(&mut *pool)
+        .await?;
+    let total: i64 = row.try_get("count")?;
+
+    let sql_query = format!(
+        "SELECT * FROM ({}) p WHERE bound>0 ORDER BY bound DESC LIMIT $2 OFFSET $3",
+        sql_latest
+    );
+    let rows = sqlx::query(&sql_query)
+        .bind(&params.0.validator)
+        .bind(page_size)
+        .bind(rows_before(page, page_size))
+        .fetch_all(&mut *pool)
+        .await?;
+    let mut positions: Vec<DelegatorPositionResponse> = vec![];
+    for r in rows {
+        positions.push(position_from_row(&r)?);
+    }
+
+    Ok(Json(QueryResult {
+        total,
+        page,
+        page_size,
+        data: positions,
+    }))
+}
@@ -0,0 +1,788 @@
+use crate::error::{IndexerError, Result};
+use crate::params::{Address, Amount, Hash};
+use crate::types::{CursorResult, ListResult, QueryResult};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::postgres::PgRow;
+use sqlx::types::chrono::{DateTime, NaiveDateTime};
+use sqlx::types::BigDecimal;
+use sqlx::{PgConnection, Postgres, QueryBuilder, Row};
+use std::str::FromStr;
+use utoipa::{IntoParams, ToSchema};
+
+const DEFAULT_PAGE_SIZE: i32 = 10;
+/// Largest `page_size` of a list endpoint
+pub const MAX_PAGE_SIZE: i32 = 1000;
+
+/// Value of a filter, always sent to the database as a bound parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Param {
+    Text(String),
+    Int(i64),
+    Bool(bool),
+    Decimal(BigDecimal),
+    Time(NaiveDateTime),
+}
+
+impl From<String> for Param {
+    fn from(v: String) -> Self {
+        Param::Text(v)
+    }
+}
+
+impl From<Address> for Param {
+    fn from(v: Address) -> Self {
+        Param::Text(v.as_str().to_string())
+    }
+}
+
+impl From<Hash> for Param {
+    fn from(v: Hash) -> Self {
+        Param::Text(v.as_str().to_string())
+    }
+}
+
+impl From<Amount> for Param {
+    fn from(v: Amount) -> Self {
+        Param::Decimal(v.0)
+    }
+}
+
+impl From<NaiveDateTime> for Param {
+    fn from(v: NaiveDateTime) -> Self {
+        Param::Time(v)
+    }
+}
+
+impl From<i64> for Param {
+    fn from(v: i64) -> Self {
+        Param::Int(v)
+    }
+}
+
+impl From<bool> for Param {
+    fn from(v: bool) -> Self {
+        Param::Bool(v)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    pub column: &'static str,
+    /// `=`, `>=` or `<=`
+    pub op: &'static str,
+    pub param: Param,
+}
+
+/// Count and page queries of a list endpoint sharing the same filters.
+/// Column names and SQL fragments are static, only filter values come from the request.
+#[derive(Debug)]
+pub struct ListQuery {
+    from: &'static str,
+    has_where: bool,
+    filters: Vec<Filter>,
+    /// Column sorted on before the key, the amount for `sort=amount`
+    sort_column: Option<&'static str>,
+    order: SortOrder,
+}
+
+impl ListQuery {
+    /// `from` is the FROM clause, e.g. `FROM evm_receipts`.
+    pub fn new(from: &'static str) -> Self {
+        Self {
+            from,
+            has_where: false,
+            filters: vec![],
+            sort_column: None,
+            order: SortOrder::Desc,
+        }
+    }
+
+    /// `from` already ends with a WHERE clause, filters are appended with AND.
+    pub fn new_filtered(from: &'static str) -> Self {
+        Self {
+            from,
+            has_where: true,
+            filters: vec![],
+            sort_column: None,
+            order: SortOrder::Desc,
+        }
+    }
+
+    /// Adds `column = value` if `value` is set.
+    pub fn eq<T: Into<Param>>(self, column: &'static str, value: Option<T>) -> Self {
+        self.cmp(column, "=", value)
+    }
+
+    /// Adds `column >= value` if `value` is set.
+    pub fn ge<T: Into<Param>>(self, column: &'static str, value: Option<T>) -> Self {
+        self.cmp(column, ">=", value)
+    }
+
+    /// Adds `column <= value` if `value` is set.
+    pub fn le<T: Into<Param>>(self, column: &'static str, value: Option<T>) -> Self {
+        self.cmp(column, "<=", value)
+    }
+
+    fn cmp<T: Into<Param>>(
+        mut self,
+        column: &'static str,
+        op: &'static str,
+        value: Option<T>,
+    ) -> Self {
+        if let Some(v) = value {
+            self.filters.push(Filter {
+                column,
+                op,
+                param: v.into(),
+            });
+        }
+        self
+    }
+
+    /// Applies the filters and sort shared by the record endpoints. `amount` is the amount
+    /// column, `None` for tables without one.
+    pub fn records(self, filter: &RecordFilter, amount: Option<&'static str>) -> Result<Self> {
+        let amount_unsupported = |param: &str| {
+            IndexerError::IndexerInvalidParam(
+                param.to_string(),
+                "not supported by this endpoint".to_string(),
+            )
+        };
+        let mut query = self
+            .ge("tm", unix_time("from_time", filter.from_time)?)
+            .le("tm", unix_time("to_time", filter.to_time)?)
+            .ge("block_num", filter.from_block)
+            .le("block_num", filter.to_block);
+        match amount {
+            Some(column) => {
+                query = query
+                    .ge(column, filter.min_amount.clone())
+                    .le(column, filter.max_amount.clone());
+            }
+            None if filter.min_amount.is_some() => return Err(amount_unsupported("min_amount")),
+            None if filter.max_amount.is_some() => return Err(amount_unsupported("max_amount")),
+            None => {}
+        }
+        query.sort_column = match filter.sort.unwrap_or_default() {
+            SortField::Amount => Some(amount.ok_or_else(|| amount_unsupported("sort"))?),
+            // Block times only grow, so time and block order are the same.
+            SortField::Block | SortField::Time => None,
+        };
+        query.order = filter.order.unwrap_or_default();
+        Ok(query)
+    }
+
+    #[cfg(test)]
+    pub fn filters(&self) -> &[Filter] {
+        &self.filters
+    }
+
+    /// `SELECT count(*) ...`, the count is in the `count` column.
+    pub fn count(&self) -> QueryBuilder<'static, Postgres> {
+        let mut qb = QueryBuilder::new("SELECT count(*) ");
+        self.push_from(&mut qb);
+        qb
+    }
+
+    /// `SELECT columns ... ORDER BY order_by LIMIT page_size OFFSET (page-1)*page_size`.
+    pub fn select(
+        &self,
+        columns: &'static str,
+        order_by: &str,
+        page: i32,
+        page_size: i32,
+    ) -> QueryBuilder<'static, Postgres> {
+        let mut qb = QueryBuilder::new("SELECT ");
+        qb.push(columns).push(" ");
+        self.push_from(&mut qb);
+        qb.push(" ORDER BY ")
+            .push(order_by)
+            .push(" LIMIT ")
+            .push_bind(page_size as i64)
+            .push(" OFFSET ")
+            .push_bind(rows_before(page, page_size));
+        qb
+    }
+
+    /// `ORDER BY` of a record query, the sort column if any, then `key`.
+    fn order_by(&self, key: &[&'static str]) -> String {
+        self.sort_column
+            .iter()
+            .chain(key.iter())
+            .map(|c| format!("{} {}", c, self.order.as_sql()))
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+
+    /// Rows after `cursor` in `key` order, at most `limit`.
+    pub fn select_after(
+        &self,
+        columns: &'static str,
+        key: &[&'static str],
+        cursor: Option<&Cursor>,
+        limit: i64,
+    ) -> QueryBuilder<'static, Postgres> {
+        let mut qb = QueryBuilder::new("SELECT ");
+        qb.push(columns).push(" ");
+        self.push_from(&mut qb);
+        if let Some(c) = cursor {
+            qb.push(if self.has_where || !self.filters.is_empty() {
+                " AND ("
+            } else {
+                " WHERE ("
+            });
+            qb.push(key.join(","));
+            qb.push(match self.order {
+                SortOrder::Asc => ")>(",
+                SortOrder::Desc => ")<(",
+            });
+            qb.push_bind(c.block_num)
+                .push(",")
+                .push_bind(c.tx_id.clone());
+            if key.len() > 2 {
+                qb.push(",").push_bind(c.log_index);
+            }
+            qb.push(")");
+        }
+        qb.push(" ORDER BY ")
+            .push(self.order_by(key))
+            .push(" LIMIT ")
+            .push_bind(limit);
+        qb
+    }
+
+    /// Planner estimate of the row count, the `QUERY PLAN` column holds the JSON plan.
+    pub fn explain_count(&self) -> QueryBuilder<'static, Postgres> {
+        let mut qb = QueryBuilder::new("EXPLAIN (FORMAT JSON) SELECT 1 ");
+        self.push_from(&mut qb);
+        qb
+    }
+
+    fn push_from(&self, qb: &mut QueryBuilder<'static, Postgres>) {
+        qb.push(self.from);
+        for (i, f) in self.filters.iter().enumerate() {
+            if i == 0 && !self.has_where {
+                qb.push(" WHERE ");
+            } else {
+                qb.push(" AND ");
+            }
+            qb.push(f.column).push(f.op);
+            match f.param {
+                Param::Text(ref v) => qb.push_bind(v.clone()),
+                Param::Int(v) => qb.push_bind(v),
+                Param::Bool(v) => qb.push_bind(v),
+                Param::Decimal(ref v) => qb.push_bind(v.clone()),
+                Param::Time(v) => qb.push_bind(v),
+            };
+        }
+    }
+}
+
+/// Order of the record endpoints, newest first. Tables with one row per transaction use the
+/// first two columns only.
+pub const RECORD_KEY: &[&str] = &["block_num", "tx_id", "log_index"];
+
+/// Position after the last row of a page, opaque to clients.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    pub block_num: i64,
+    pub tx_id: String,
+    pub log_index: i32,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!(
+            "{}:{}:{}",
+            self.block_num, self.tx_id, self.log_index
+        ))
+    }
+
+    fn from_row(r: &PgRow, key: &[&'static str]) -> Result<Self> {
+        Ok(Cursor {
+            block_num: r.try_get("block_num")?,
+            tx_id: r.try_get("tx_id")?,
+            log_index: if key.len() > 2 {
+                r.try_get("log_index")?
+            } else {
+                0
+            },
+        })
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(s)
+            .map_err(|_| "malformed cursor".to_string())?;
+        let text = String::from_utf8(bytes).map_err(|_| "malformed cursor".to_string())?;
+        let mut parts = text.splitn(3, ':');
+        let (Some(block_num), Some(tx_id), Some(log_index)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err("malformed cursor".to_string());
+        };
+        Ok(Cursor {
+            block_num: block_num
+                .parse()
+                .map_err(|_| "malformed cursor".to_string())?,
+            tx_id: tx_id.to_string(),
+            log_index: log_index
+                .parse()
+                .map_err(|_| "malformed cursor".to_string())?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortField {
+    Amount,
+    #[default]
+    Block,
+    Time,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+}
+
+/// Filters and sort accepted by every record endpoint, times are unix timestamps.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RecordFilter {
+    pub from_time: Option<i64>,
+    pub to_time: Option<i64>,
+    pub from_block: Option<i64>,
+    pub to_block: Option<i64>,
+    pub min_amount: Option<Amount>,
+    pub max_amount: Option<Amount>,
+    pub sort: Option<SortField>,
+    pub order: Option<SortOrder>,
+}
+
+fn unix_time(param: &str, t: Option<i64>) -> Result<Option<NaiveDateTime>> {
+    t.map(|t| {
+        DateTime::from_timestamp(t, 0)
+            .map(|d| d.naive_utc())
+            .ok_or_else(|| {
+                IndexerError::IndexerInvalidParam(param.to_string(), "out of range".to_string())
+            })
+    })
+    .transpose()
+}
+
+/// How the total of a cursor page is computed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Total {
+    /// `count(*)`, slow on large tables
+    Exact,
+    /// Planner estimate
+    Approx,
+    #[default]
+    None,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Page {
+    Offset {
+        page: i32,
+        page_size: i32,
+    },
+    Cursor {
+        after: Option<Cursor>,
+        page_size: i32,
+        total: Total,
+    },
+}
+
+impl Page {
+    /// Cursor mode as soon as `cursor` is given, an empty cursor asks for the first page.
+    /// Offset pages start at 1.
+    pub fn new(
+        page: Option<i32>,
+        page_size: Option<i32>,
+        cursor: Option<&str>,
+        total: Option<Total>,
+    ) -> Result<Self> {
+        match cursor {
+            None => {
+                let (page, page_size) = offset_page(page, page_size)?;
+                Ok(Page::Offset { page, page_size })
+            }
+            Some(c) => {
+                let page_size = checked_page_size(page_size)?;
+                Ok(Page::Cursor {
+                    after: match c {
+                        "" => None,
+                        c => Some(Cursor::from_str(c).map_err(|e| {
+                            IndexerError::IndexerInvalidParam("cursor".to_string(), e)
+                        })?),
+                    },
+                    page_size,
+                    total: total.unwrap_or_default(),
+                })
+            }
+        }
+    }
+}
+
+/// `(page, page_size)` of an offset page, the only mode of endpoints without cursors.
+pub fn offset_page(page: Option<i32>, page_size: Option<i32>) -> Result<(i32, i32)> {
+    let page = page.unwrap_or(1);
+    if page <= 0 {
+        return Err(IndexerError::IndexerInvalidParam(
+            "page".to_string(),
+            "must be at least 1".to_string(),
+        ));
+    }
+    Ok((page, checked_page_size(page_size)?))
+}
+
+fn checked_page_size(page_size: Option<i32>) -> Result<i32> {
+    let page_size = page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&page_size) {
+        return Err(IndexerError::IndexerInvalidParam(
+            "page_size".to_string(),
+            format!("must be between 1 and {}", MAX_PAGE_SIZE),
+        ));
+    }
+    Ok(page_size)
+}
+
+/// Rows skipped before offset page `page`, computed in i64 so deep pages don't overflow.
+pub fn rows_before(page: i32, page_size: i32) -> i64 {
+    (page as i64 - 1) * page_size as i64
+}
+
+/// Runs `query` in the mode asked by `page`, ordered by `key` newest first. `columns` must
+/// include the key columns.
+pub async fn fetch_records<T>(
+    conn: &mut PgConnection,
+    query: &ListQuery,
+    columns: &'static str,
+    key: &'static [&'static str],
+    page: Page,
+    map: impl Fn(&PgRow) -> Result<T>,
+) -> Result<ListResult<Vec<T>>> {
+    match page {
+        Page::Offset { page, page_size } => {
+            let row = query.count().build().fetch_one(&mut *conn).await?;
+            let total: i64 = row.try_get("count")?;
+            let rows = query
+                .select(columns, &query.order_by(key), page, page_size)
+                .build()
+                .fetch_all(&mut *conn)
+                .await?;
+            Ok(ListResult::Page(QueryResult {
+                total,
+                page,
+                page_size,
+                data: rows.iter().map(map).collect::<Result<Vec<T>>>()?,
+            }))
+        }
+        Page::Cursor {
+            after,
+            page_size,
+            total,
+        } => {
+            if query.sort_column.is_some() {
+                return Err(IndexerError::IndexerInvalidParam(
+                    "sort".to_string(),
+                    "only block and time sorts support cursors".to_string(),
+                ));
+            }
+            let total = match total {
+                Total::Exact => {
+                    let row = query.count().build().fetch_one(&mut *conn).await?;
+                    Some(row.try_get("count")?)
+                }
+                Total::Approx => {
+                    let row = query.explain_count().build().fetch_one(&mut *conn).await?;
+                    let plan: Value = row.try_get(0)?;
+                    plan[0]["Plan"]["Plan Rows"].as_f64().map(|n| n as i64)
+                }
+                Total::None => None,
+            };
+            // One extra row tells whether there is a next page.
+            let mut rows = query
+                .select_after(columns, key, after.as_ref(), page_size as i64 + 1)
+                .build()
+                .fetch_all(&mut *conn)
+                .await?;
+            let next_cursor = if rows.len() > page_size as usize {
+                rows.truncate(page_size as usize);
+                match rows.last() {
+                    Some(r) => Some(Cursor::from_row(r, key)?.encode()),
+                    None => None,
+                }
+            } else {
+                None
+            };
+            Ok(ListResult::Cursor(CursorResult {
+                total,
+                page_size,
+                next_cursor,
+                data: rows.iter().map(map).collect::<Result<Vec<T>>>()?,
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Asserts that `query` selects `from` filtered on `filters` in order, each a column, an
+    /// operator and the bound value.
+    pub(crate) fn assert_filters(
+        query: &ListQuery,
+        from: &str,
+        filters: &[(&'static str, &'static str, Param)],
+    ) {
+        let clauses = filters
+            .iter()
+            .enumerate()
+            .map(|(i, (column, op, _))| format!("{}{}${}", column, op, i + 1))
+            .collect::<Vec<_>>();
+        let mut sql = format!("SELECT count(*) {}", from);
+        if !clauses.is_empty() {
+            sql = format!("{} WHERE {}", sql, clauses.join(" AND "));
+        }
+        assert_eq!(query.count().sql(), sql);
+        let expected = filters
+            .iter()
+            .map(|(column, op, param)| Filter {
+                column,
+                op,
+                param: param.clone(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(query.filters(), expected);
+    }
+
+    #[test]
+    fn no_filters() {
+        let q = ListQuery::new("FROM t");
+        assert_eq!(q.count().sql(), "SELECT count(*) FROM t");
+        assert_eq!(
+            q.select("a,b", "a DESC", 2, 10).sql(),
+            "SELECT a,b FROM t ORDER BY a DESC LIMIT $1 OFFSET $2"
+        );
+    }
+
+    #[test]
+    fn filters_are_bound() {
+        let q = ListQuery::new("FROM t")
+            .eq("a", Some("x' OR '1'='1".to_string()))
+            .eq::<i64>("b", None)
+            .eq("c", Some(true));
+        assert_eq!(
+            q.count().sql(),
+            "SELECT count(*) FROM t WHERE a=$1 AND c=$2"
+        );
+        assert_eq!(
+            q.filters(),
+            [
+                Filter {
+                    column: "a",
+                    op: "=",
+                    param: Param::Text("x' OR '1'='1".to_string())
+                },
+                Filter {
+                    column: "c",
+                    op: "=",
+                    param: Param::Bool(true)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_keep_call_order() {
+        let q = ListQuery::new("FROM t")
+            .eq::<String>("a", None)
+            .eq("b", Some("y".to_string()))
+            .eq("a", Some("x".to_string()));
+        assert_eq!(
+            q.count().sql(),
+            "SELECT count(*) FROM t WHERE b=$1 AND a=$2"
+        );
+        assert_eq!(q.filters()[1].param, Param::Text("x".to_string()));
+    }
+
+    #[test]
+    fn filters_after_where() {
+        let q = ListQuery::new_filtered("FROM t WHERE x=1").eq("a", Some(1i64));
+        assert_eq!(q.count().sql(), "SELECT count(*) FROM t WHERE x=1 AND a=$1");
+    }
+
+    #[test]
+    fn cursor_round_trip() {
+        let c = Cursor {
+            block_num: 12,
+            tx_id: "0xab".to_string(),
+            log_index: 3,
+        };
+        assert_eq!(Cursor::from_str(&c.encode()), Ok(c));
+        assert!(Cursor::from_str("not a cursor").is_err());
+    }
+
+    #[test]
+    fn select_after_cursor() {
+        let c = Cursor {
+            block_num: 12,
+            tx_id: "0xab".to_string(),
+            log_index: 3,
+        };
+        let q = ListQuery::new("FROM t");
+        assert_eq!(
+            q.select_after("a", RECORD_KEY, Some(&c), 11).sql(),
+            "SELECT a FROM t WHERE (block_num,tx_id,log_index)<($1,$2,$3) \
+            ORDER BY block_num DESC,tx_id DESC,log_index DESC LIMIT $4"
+        );
+        let q = q.eq("a", Some(true));
+        assert_eq!(
+            q.select_after("a", &RECORD_KEY[..2], Some(&c), 11).sql(),
+            "SELECT a FROM t WHERE a=$1 AND (block_num,tx_id)<($2,$3) \
+            ORDER BY block_num DESC,tx_id DESC LIMIT $4"
+        );
+    }
+
+    #[test]
+    fn page_mode() {
+        assert_eq!(
+            Page::new(None, None, None, Some(Total::Exact)).unwrap(),
+            Page::Offset {
+                page: 1,
+                page_size: 10
+            }
+        );
+        assert_eq!(
+            Page::new(None, Some(5), Some(""), None).unwrap(),
+            Page::Cursor {
+                after: None,
+                page_size: 5,
+                total: Total::None
+            }
+        );
+        assert!(Page::new(None, None, Some("@@"), None).is_err());
+        assert!(Page::new(Some(0), None, None, None).is_err());
+        assert!(Page::new(Some(-1), None, None, None).is_err());
+        assert!(Page::new(None, Some(0), None, None).is_err());
+        assert!(Page::new(None, Some(MAX_PAGE_SIZE + 1), Some(""), None).is_err());
+        assert!(Page::new(Some(i32::MAX), Some(MAX_PAGE_SIZE), None, None).is_ok());
+        assert_eq!(
+            rows_before(i32::MAX, MAX_PAGE_SIZE),
+            (i32::MAX as i64 - 1) * MAX_PAGE_SIZE as i64
+        );
+        // Cursor pages ignore `page`.
+        assert!(Page::new(Some(0), None, Some(""), None).is_ok());
+    }
+
+    fn record_query(filter: RecordFilter) -> ListQuery {
+        ListQuery::new("FROM t")
+            .records(&filter, Some("amount"))
+            .unwrap()
+    }
+
+    #[test]
+    fn record_time_range() {
+        let q = record_query(RecordFilter {
+            from_time: Some(0),
+            to_time: Some(86400),
+            ..Default::default()
+        });
+        assert_eq!(
+            q.count().sql(),
+            "SELECT count(*) FROM t WHERE tm>=$1 AND tm<=$2"
+        );
+        assert_eq!(
+            q.filters()[1].param,
+            Param::Time(DateTime::from_timestamp(86400, 0).unwrap().naive_utc())
+        );
+    }
+
+    #[test]
+    fn record_block_range() {
+        let q = record_query(RecordFilter {
+            from_block: Some(5),
+            to_block: Some(9),
+            ..Default::default()
+        });
+        assert_eq!(
+            q.count().sql(),
+            "SELECT count(*) FROM t WHERE block_num>=$1 AND block_num<=$2"
+        );
+        assert_eq!(q.filters()[0].param, Param::Int(5));
+    }
+
+    #[test]
+    fn record_amount_range() {
+        let q = record_query(RecordFilter {
+            min_amount: Some("1".parse().unwrap()),
+            max_amount: Some("1000000000000000000000".parse().unwrap()),
+            ..Default::default()
+        });
+        assert_eq!(
+            q.count().sql(),
+            "SELECT count(*) FROM t WHERE amount>=$1 AND amount<=$2"
+        );
+        let no_amount = ListQuery::new("FROM t").records(
+            &RecordFilter {
+                min_amount: Some("1".parse().unwrap()),
+                ..Default::default()
+            },
+            None,
+        );
+        assert!(no_amount.is_err());
+    }
+
+    #[test]
+    fn record_sort() {
+        let q = record_query(RecordFilter {
+            sort: Some(SortField::Amount),
+            order: Some(SortOrder::Asc),
+            ..Default::default()
+        });
+        assert_eq!(
+            q.order_by(RECORD_KEY),
+            "amount ASC,block_num ASC,tx_id ASC,log_index ASC"
+        );
+        let q = record_query(RecordFilter {
+            sort: Some(SortField::Time),
+            order: Some(SortOrder::Asc),
+            ..Default::default()
+        });
+        let c = Cursor {
+            block_num: 1,
+            tx_id: "0xab".to_string(),
+            log_index: 0,
+        };
+        assert_eq!(
+            q.select_after("a", RECORD_KEY, Some(&c), 2).sql(),
+            "SELECT a FROM t WHERE (block_num,tx_id,log_index)>($1,$2,$3) \
+            ORDER BY block_num ASC,tx_id ASC,log_index ASC LIMIT $4"
+        );
+    }
+}
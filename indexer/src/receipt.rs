@@ -1,103 +1,152 @@
 use crate::error::Result;
-use crate::types::{QueryResult, ReceiptResponse};
+use crate::params::{Address, Hash, Query};
+use crate::query::{fetch_records, ListQuery, Page, RecordFilter, Total, RECORD_KEY};
+use crate::types::{ErrorResponse, ListResult, ReceiptResponse};
 use crate::AppState;
-use axum::extract::{Query, State};
+use axum::extract::State;
 use axum::Json;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sqlx::types::chrono::NaiveDateTime;
 use sqlx::Row;
-use std::ops::Add;
 use std::sync::Arc;
+use utoipa::IntoParams;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct GetReceiptsParams {
-    pub txid: Option<String>,
-    pub blockid: Option<String>,
-    pub blocknum: Option<String>,
-    pub from: Option<String>,
-    pub to: Option<String>,
+    pub txid: Option<Hash>,
+    pub blockid: Option<Hash>,
+    pub blocknum: Option<i64>,
+    pub from: Option<Address>,
+    pub to: Option<Address>,
     pub page: Option<i32>,
     pub page_size: Option<i32>,
+    pub cursor: Option<String>,
+    pub total: Option<Total>,
 }
 
+fn receipts_query(params: &GetReceiptsParams) -> ListQuery {
+    ListQuery::new("FROM evm_receipts")
+        .eq("tx_id", params.txid.clone())
+        .eq("block_id", params.blockid.clone())
+        .eq("block_num", params.blocknum)
+        .eq("from_addr", params.from.clone())
+        .eq("to_addr", params.to.clone())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/receipts",
+    tag = "receipt",
+    params(GetReceiptsParams, RecordFilter),
+    responses(
+        (status = 200, body = ListResult<Vec<ReceiptResponse>>),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 pub async fn get_receipts(
     State(state): State<Arc<AppState>>,
     params: Query<GetReceiptsParams>,
-) -> Result<Json<QueryResult<Vec<ReceiptResponse>>>> {
+    filter: Query<RecordFilter>,
+) -> Result<Json<ListResult<Vec<ReceiptResponse>>>> {
     let mut pool = state.pool.acquire().await?;
-    let page = params.page.unwrap_or(1);
-    let page_size = params.page_size.unwrap_or(10);
+    let page = Page::new(
+        params.page,
+        params.page_size,
+        params.cursor.as_deref(),
+        params.total,
+    )?;
 
-    let mut sql_total = "SELECT count(block_num) FROM evm_receipts ".to_string();
-    let mut sql_query =
-        "SELECT tx_id,block_id,block_num,from_addr,to_addr,tm,value FROM evm_receipts ".to_string();
+    let query = receipts_query(&params).records(&filter, None)?;
+    let res = fetch_records(
+        &mut pool,
+        &query,
+        "tx_id,block_id,block_num,from_addr,to_addr,tm,value",
+        &RECORD_KEY[..2],
+        page,
+        |r| {
+            let tx_id: String = r.try_get("tx_id")?;
+            let block_id: String = r.try_get("block_id")?;
+            let block_num: i64 = r.try_get("block_num")?;
+            let from: String = r.try_get("from_addr")?;
+            let to: String = r.try_get("to_addr")?;
+            let tm: NaiveDateTime = r.try_get("tm")?;
+            let value: Value = r.try_get("value")?;
 
-    let mut query_params: Vec<String> = vec![];
-    if let Some(tx_id) = params.0.txid {
-        query_params.push(format!("tx_id='{}' ", tx_id))
-    }
-    if let Some(block_id) = params.0.blockid {
-        query_params.push(format!("block_id='{}' ", block_id))
-    }
-    if let Some(block_num) = params.0.blocknum {
-        query_params.push(format!("block_num={} ", block_num))
-    }
-    if let Some(from) = params.0.from {
-        query_params.push(format!("from_addr='{}' ", from))
-    }
-    if let Some(to) = params.0.to {
-        query_params.push(format!("to='{}' ", to))
-    }
+            Ok(ReceiptResponse {
+                tx_id,
+                block_id,
+                block_num,
+                from,
+                to,
+                datetime: tm.to_string(),
+                timestamp: tm.and_utc().timestamp(),
+                value,
+            })
+        },
+    )
+    .await?;
 
-    if !query_params.is_empty() {
-        sql_total = sql_total
-            .add("WHERE ")
-            .add(query_params.join("AND ").as_str());
-        sql_query = sql_query
-            .add("WHERE ")
-            .add(query_params.join("AND ").as_str());
-    }
+    Ok(Json(res))
+}
 
-    sql_query = sql_query.add(
-        format!(
-            "ORDER BY tm DESC LIMIT {} OFFSET {}",
-            page_size,
-            (page - 1) * page_size
-        )
-        .as_str(),
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::tests::assert_filters;
+    use crate::query::Param;
 
-    let row = sqlx::query(&sql_total).fetch_one(&mut *pool).await?;
-    let total: i64 = row.try_get("count")?;
+    const FROM: &str = "FROM evm_receipts";
+    const ADDR: &str = "0x00000000000000000000000000000000000000ab";
+    const HASH: &str = "0x00000000000000000000000000000000000000000000000000000000000000cd";
 
-    let mut receipts: Vec<ReceiptResponse> = vec![];
-    let rows = sqlx::query(&sql_query).fetch_all(&mut *pool).await?;
-    for r in rows {
-        let tx_id: String = r.try_get("tx_id")?;
-        let block_id: String = r.try_get("block_id")?;
-        let block_num: i64 = r.try_get("block_num")?;
-        let from: String = r.try_get("from_addr")?;
-        let to: String = r.try_get("to_addr")?;
-        let tm: NaiveDateTime = r.try_get("tm")?;
-        let value: Value = r.try_get("value")?;
+    #[test]
+    fn filter_txid() {
+        let query = receipts_query(&GetReceiptsParams {
+            txid: Some(HASH.parse().unwrap()),
+            ..Default::default()
+        });
+        assert_filters(&query, FROM, &[("tx_id", "=", Param::Text(HASH.into()))]);
+    }
 
-        receipts.push(ReceiptResponse {
-            tx_id,
-            block_id,
-            block_num,
-            from,
-            to,
-            datetime: tm.to_string(),
-            timestamp: tm.and_utc().timestamp(),
-            value,
-        })
+    #[test]
+    fn filter_blockid() {
+        let query = receipts_query(&GetReceiptsParams {
+            blockid: Some(HASH.parse().unwrap()),
+            ..Default::default()
+        });
+        assert_filters(&query, FROM, &[("block_id", "=", Param::Text(HASH.into()))]);
     }
 
-    Ok(Json(QueryResult {
-        total,
-        page,
-        page_size,
-        data: receipts,
-    }))
+    #[test]
+    fn filter_blocknum() {
+        let query = receipts_query(&GetReceiptsParams {
+            blocknum: Some(42),
+            ..Default::default()
+        });
+        assert_filters(&query, FROM, &[("block_num", "=", Param::Int(42))]);
+    }
+
+    #[test]
+    fn filter_from() {
+        let query = receipts_query(&GetReceiptsParams {
+            from: Some(ADDR.parse().unwrap()),
+            ..Default::default()
+        });
+        assert_filters(
+            &query,
+            FROM,
+            &[("from_addr", "=", Param::Text(ADDR.into()))],
+        );
+    }
+
+    #[test]
+    fn filter_to() {
+        let query = receipts_query(&GetReceiptsParams {
+            to: Some(ADDR.parse().unwrap()),
+            ..Default::default()
+        });
+        assert_filters(&query, FROM, &[("to_addr", "=", Param::Text(ADDR.into()))]);
+    }
 }
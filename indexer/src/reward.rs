@@ -0,0 +1,123 @@
+use crate::error::Result;
+use crate::params::{Address, Query};
+use crate::query::{offset_page, rows_before};
+use crate::types::{ErrorResponse, QueryResult, RewardSplitResponse};
+use crate::AppState;
+use axum::extract::State;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgRow;
+use sqlx::types::chrono::NaiveDateTime;
+use sqlx::types::BigDecimal;
+use sqlx::Row;
+use std::sync::Arc;
+use utoipa::IntoParams;
+
+fn split_from_row(r: &PgRow) -> Result<RewardSplitResponse> {
+    let tm: NaiveDateTime = r.try_get("tm")?;
+    let staker_reward_ratio: BigDecimal = r.try_get("staker_reward_ratio")?;
+    let delegator_reward_ratio: BigDecimal = r.try_get("delegator_reward_ratio")?;
+    let commission_unclaimed: BigDecimal = r.try_get("commission_unclaimed")?;
+    let delegators_unclaimed: BigDecimal = r.try_get("delegators_unclaimed")?;
+    let commission_earned: BigDecimal = r.try_get("commission_earned")?;
+    let delegators_earned: BigDecimal = r.try_get("delegators_earned")?;
+    Ok(RewardSplitResponse {
+        validator: r.try_get("validator")?,
+        block_num: r.try_get("block_num")?,
+        timestamp: tm.and_utc().timestamp(),
+        staker_reward_ratio: staker_reward_ratio.to_string(),
+        delegator_reward_ratio: delegator_reward_ratio.to_string(),
+        commission_unclaimed: commission_unclaimed.to_string(),
+        delegators_unclaimed: delegators_unclaimed.to_string(),
+        delegators: r.try_get("delegators")?,
+        commission_earned: commission_earned.to_string(),
+        delegators_earned: delegators_earned.to_string(),
+    })
+}
+
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RewardSplitParams {
+    pub validator: Address,
+}
+
+/// Latest reward split of a validator.
+/// Compare the `*_earned` amounts, the unclaimed ones drop to zero on claim.
+#[utoipa::path(
+    get,
+    path = "/api/reward/split",
+    tag = "reward",
+    params(RewardSplitParams),
+    responses(
+        (status = 200, body = RewardSplitResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
+pub async fn get_reward_split(
+    State(state): State<Arc<AppState>>,
+    params: Query<RewardSplitParams>,
+) -> Result<Json<RewardSplitResponse>> {
+    let mut pool = state.pool.acquire().await?;
+    let sql_query = r#"SELECT * FROM evm_reward_snapshots WHERE validator=$1
+        ORDER BY block_num DESC LIMIT 1"#;
+    let row = sqlx::query(sql_query)
+        .bind(params.0.validator.clone())
+        .fetch_one(&mut *pool)
+        .await?;
+
+    Ok(Json(split_from_row(&row)?))
+}
+
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RewardSplitHistoryParams {
+    pub validator: Address,
+    pub page: Option<i32>,
+    pub page_size: Option<i32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/reward/split/history",
+    tag = "reward",
+    params(RewardSplitHistoryParams),
+    responses(
+        (status = 200, body = QueryResult<Vec<RewardSplitResponse>>),
+        (status = 400, body = ErrorResponse),
+    )
+)]
+pub async fn get_reward_split_history(
+    State(state): State<Arc<AppState>>,
+    params: Query<RewardSplitHistoryParams>,
+) -> Result<Json<QueryResult<Vec<RewardSplitResponse>>>> {
+    let mut pool = state.pool.acquire().await?;
+    let (page, page_size) = offset_page(params.page, params.page_size)?;
+    let validator = params.0.validator.clone();
+
+    let sql_total = r#"SELECT count(*) FROM evm_reward_snapshots WHERE validator=$1"#;
+    let row = sqlx::query(sql_total)
+        .bind(&validator)
+        .fetch_one(&mut *pool)
+        .await?;
+    let total: i64 = row.try_get("count")?;
+
+    let sql_query = r#"SELECT * FROM evm_reward_snapshots WHERE validator=$1
+        ORDER BY block_num DESC LIMIT $2 OFFSET $3"#;
+    let rows = sqlx::query(sql_query)
+        .bind(&validator)
+        .bind(page_size)
+        .bind(rows_before(page, page_size))
+        .fetch_all(&mut *pool)
+        .await?;
+    let mut splits: Vec<RewardSplitResponse> = vec![];
+    for r in rows {
+        splits.push(split_from_row(&r)?);
+    }
+
+    Ok(Json(QueryResult {
+        total,
+        page,
+        page_size,
+        data: splits,
+    }))
+}
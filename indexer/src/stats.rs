@@ -0,0 +1,137 @@
+use crate::error::{IndexerError, Result};
+use crate::params::Query;
+use crate::types::{ErrorResponse, NetworkStatsResponse};
+use crate::AppState;
+use axum::extract::State;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::types::BigDecimal;
+use sqlx::Row;
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+const DEFAULT_STATS_RANGE: i64 = 7 * 24 * 60 * 60; // 7 days
+const MAX_STATS_POINTS: i64 = 2000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Resolution {
+    /// Every recorded sample
+    Raw,
+    #[default]
+    Hour,
+    Day,
+    Week,
+}
+
+impl Resolution {
+    fn unit(&self) -> &'static str {
+        match self {
+            Resolution::Raw => "microseconds",
+            Resolution::Hour => "hour",
+            Resolution::Day => "day",
+            Resolution::Week => "week",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NetworkStatsParams {
+    /// Unix timestamp, `end` minus 7 days by default
+    pub start: Option<i64>,
+    /// Unix timestamp, now by default
+    pub end: Option<i64>,
+    pub resolution: Option<Resolution>,
+}
+
+/// Network-wide figures between `start` and `end`, oldest first.
+/// With a resolution other than `raw` the last sample of each bucket is returned.
+/// A range with more than `MAX_STATS_POINTS` points is rejected rather than truncated.
+#[utoipa::path(
+    get,
+    path = "/api/stats/network",
+    tag = "stats",
+    params(NetworkStatsParams),
+    responses(
+        (status = 200, body = Vec<NetworkStatsResponse>),
+        (status = 400, body = ErrorResponse),
+    )
+)]
+pub async fn get_network_stats(
+    State(state): State<Arc<AppState>>,
+    params: Query<NetworkStatsParams>,
+) -> Result<Json<Vec<NetworkStatsResponse>>> {
+    let mut pool = state.pool.acquire().await?;
+    let end = params.end.unwrap_or(Utc::now().timestamp());
+    let start = params.start.unwrap_or(end - DEFAULT_STATS_RANGE);
+    let to_datetime = |param: &str, t: i64| {
+        DateTime::from_timestamp(t, 0).map(|d| d.naive_utc()).ok_or(
+            IndexerError::IndexerInvalidParam(
+                param.to_string(),
+                format!("invalid timestamp: {}", t),
+            ),
+        )
+    };
+    let (start_tm, end_tm) = (to_datetime("start", start)?, to_datetime("end", end)?);
+    if start_tm > end_tm {
+        return Err(IndexerError::IndexerInvalidParam(
+            "start".to_string(),
+            "must not be after end".to_string(),
+        ));
+    }
+    let resolution = params.resolution.unwrap_or_default();
+
+    let columns = "block_num,tm,total_delegation,global_pre_issue,return_rate,\
+        active_validators,inactive_validators,total_power";
+    let sql_query = match resolution {
+        Resolution::Raw => format!(
+            "SELECT {} FROM evm_network_stats WHERE tm>=$1 AND tm<=$2 ORDER BY tm LIMIT $3",
+            columns
+        ),
+        r => format!(
+            "SELECT * FROM (SELECT DISTINCT ON (date_trunc('{}',tm)) {} FROM evm_network_stats \
+            WHERE tm>=$1 AND tm<=$2 ORDER BY date_trunc('{}',tm),tm DESC) s ORDER BY tm LIMIT $3",
+            r.unit(),
+            columns,
+            r.unit()
+        ),
+    };
+    let rows = sqlx::query(&sql_query)
+        .bind(start_tm)
+        .bind(end_tm)
+        .bind(MAX_STATS_POINTS + 1)
+        .fetch_all(&mut *pool)
+        .await?;
+    if rows.len() as i64 > MAX_STATS_POINTS {
+        return Err(IndexerError::IndexerInvalidParam(
+            "resolution".to_string(),
+            format!(
+                "more than {} points in range, narrow it or use a coarser resolution",
+                MAX_STATS_POINTS
+            ),
+        ));
+    }
+
+    let mut stats: Vec<NetworkStatsResponse> = vec![];
+    for r in rows {
+        let tm: NaiveDateTime = r.try_get("tm")?;
+        let total_delegation: BigDecimal = r.try_get("total_delegation")?;
+        let global_pre_issue: BigDecimal = r.try_get("global_pre_issue")?;
+        let return_rate: BigDecimal = r.try_get("return_rate")?;
+        let total_power: BigDecimal = r.try_get("total_power")?;
+        stats.push(NetworkStatsResponse {
+            block_num: r.try_get("block_num")?,
+            timestamp: tm.and_utc().timestamp(),
+            total_delegation: total_delegation.to_string(),
+            global_pre_issue: global_pre_issue.to_string(),
+            return_rate: return_rate.to_string(),
+            active_validators: r.try_get("active_validators")?,
+            inactive_validators: r.try_get("inactive_validators")?,
+            total_power: total_power.to_string(),
+        })
+    }
+
+    Ok(Json(stats))
+}
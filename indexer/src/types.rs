@@ -1,7 +1,8 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct QueryResult<T> {
     pub total: i64,
     pub page: i32,
@@ -9,7 +10,32 @@ pub struct QueryResult<T> {
     pub data: T,
 }
 
-#[derive(Serialize, Deserialize)]
+/// Page of a record endpoint in cursor mode. `next_cursor` is absent on the last page.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CursorResult<T> {
+    pub total: Option<i64>,
+    pub page_size: i32,
+    pub next_cursor: Option<String>,
+    pub data: T,
+}
+
+/// Record endpoints answer in page/page_size or in cursor mode.
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum ListResult<T> {
+    Page(#[schema(inline)] QueryResult<T>),
+    Cursor(#[schema(inline)] CursorResult<T>),
+}
+
+/// Body of every error response.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+    pub request_id: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct MintResponse {
     pub block_num: i64,
     pub timestamp: i64,
@@ -19,42 +45,42 @@ pub struct MintResponse {
     pub amount: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct ValidatorSumRewardResponse {
     pub reward: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct ValidatorVoteResponse {
     pub block_num: i64,
     pub should_vote: i32,
     pub voted: i32,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct DelegatorSumResponse {
     pub delegate: String,
     pub undelegate: String,
     pub claim: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct BoundResponse {
     pub bound_amount: String,
     pub unbound_amount: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct RewardResponse {
     pub reward: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct DebtResponse {
     pub debt: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct ValidatorLatest20Response {
     pub block_num: i64,
     pub total: String,
@@ -63,7 +89,7 @@ pub struct ValidatorLatest20Response {
     pub op: i32,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct ValidatorResponse {
     pub validator: String,
     pub staker: String,
@@ -82,7 +108,28 @@ pub struct ValidatorResponse {
     pub memo: Value,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ValidatorProfileResponse {
+    pub tx_id: String,
+    pub block_num: i64,
+    pub timestamp: i64,
+    pub memo: Value,
+    pub rate: String,
+    pub op: i32,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ValidatorTimelineResponse {
+    pub block_num: i64,
+    pub timestamp: i64,
+    /// `transition` derived by the updater, or `event` emitted by the Staking contract
+    pub source: String,
+    pub kind: String,
+    pub tx_id: Option<String>,
+    pub detail: Value,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct ReceiptResponse {
     pub tx_id: String,
     pub block_id: String,
@@ -94,7 +141,7 @@ pub struct ReceiptResponse {
     pub value: Value,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct StakeResponse {
     pub tx_id: String,
     pub block_id: String,
@@ -110,7 +157,7 @@ pub struct StakeResponse {
     pub memo: Value,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct DelegateResponse {
     pub tx_hash: String,
     pub block_hash: String,
@@ -121,7 +168,7 @@ pub struct DelegateResponse {
     pub timestamp: i64,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct UndelegateResponse {
     pub tx_hash: String,
     pub block_hash: String,
@@ -132,14 +179,14 @@ pub struct UndelegateResponse {
     pub timestamp: i64,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct DelegatorOfValidatorResponse {
     pub delegator: String,
     pub amount: String,
     pub rank: i64,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct ValidatorDataResponse {
     pub public_key: String,
     pub public_key_type: u8,
@@ -150,7 +197,7 @@ pub struct ValidatorDataResponse {
     pub begin_block: u64,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct ValidatorStatusResponse {
     pub heap_index_off1: String,
     pub is_active: bool,
@@ -159,3 +206,57 @@ pub struct ValidatorStatusResponse {
     pub should_vote: u16,
     pub voted: u16,
 }
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct DelegatorPositionResponse {
+    pub validator: String,
+    pub delegator: String,
+    pub block_num: i64,
+    pub timestamp: i64,
+    pub bound: String,
+    pub unbound: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PendingUndelegationResponse {
+    pub index: i64,
+    pub tx_hash: Option<String>,
+    pub validator: String,
+    pub delegator: String,
+    pub amount: String,
+    pub unlock_time: i64,
+    /// Seconds until the amount unlocks, 0 once unlocked
+    pub unlock_in: i64,
+    pub released: bool,
+    pub released_block: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct NetworkStatsResponse {
+    pub block_num: i64,
+    pub timestamp: i64,
+    pub total_delegation: String,
+    pub global_pre_issue: String,
+    pub return_rate: String,
+    pub active_validators: i32,
+    pub inactive_validators: i32,
+    pub total_power: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RewardSplitResponse {
+    pub validator: String,
+    pub block_num: i64,
+    pub timestamp: i64,
+    pub staker_reward_ratio: String,
+    pub delegator_reward_ratio: String,
+    /// Commission the validator earned and has not claimed
+    pub commission_unclaimed: String,
+    /// Rewards its delegators earned and have not claimed
+    pub delegators_unclaimed: String,
+    pub delegators: i32,
+    /// Commission the validator earned so far, claimed or not
+    pub commission_earned: String,
+    /// Rewards its delegators earned so far, claimed or not
+    pub delegators_earned: String,
+}
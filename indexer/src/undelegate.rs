@@ -1,126 +1,235 @@
 use crate::error::Result;
-use crate::types::{QueryResult, UndelegateResponse};
+use crate::params::{Address, Query};
+use crate::query::{
+    fetch_records, offset_page, rows_before, ListQuery, Page, RecordFilter, Total, RECORD_KEY,
+};
+use crate::types::{
+    ErrorResponse, ListResult, PendingUndelegationResponse, QueryResult, UndelegateResponse,
+};
 use crate::AppState;
-use axum::extract::{Query, State};
+use axum::extract::State;
 use axum::Json;
 use serde::{Deserialize, Serialize};
-use sqlx::types::chrono::NaiveDateTime;
+use sqlx::types::chrono::{NaiveDateTime, Utc};
 use sqlx::types::BigDecimal;
 use sqlx::Row;
 use std::sync::Arc;
+use utoipa::IntoParams;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct DelegatorUndelegateRecordsParams {
-    pub delegator: Option<String>,
+    pub delegator: Option<Address>,
+    pub validator: Option<Address>,
     pub page: Option<i32>,
     pub page_size: Option<i32>,
+    pub cursor: Option<String>,
+    pub total: Option<Total>,
 }
 
+fn delegator_undelegate_query(params: &DelegatorUndelegateRecordsParams) -> ListQuery {
+    ListQuery::new("FROM evm_undelegations")
+        .eq("delegator", params.delegator.clone())
+        .eq("validator", params.validator.clone())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/records/undelegate/delegator",
+    tag = "undelegate",
+    params(DelegatorUndelegateRecordsParams, RecordFilter),
+    responses(
+        (status = 200, body = ListResult<Vec<UndelegateResponse>>),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 pub async fn get_delegator_undelegate_records(
     State(state): State<Arc<AppState>>,
     params: Query<DelegatorUndelegateRecordsParams>,
-) -> Result<Json<QueryResult<Vec<UndelegateResponse>>>> {
+    filter: Query<RecordFilter>,
+) -> Result<Json<ListResult<Vec<UndelegateResponse>>>> {
     let mut pool = state.pool.acquire().await?;
-    let page = params.page.unwrap_or(1);
-    let page_size = params.page_size.unwrap_or(10);
-
-    let (sql_total, sql_query) = if let Some(delegator) = params.0.delegator {
-        let delegator_lower = delegator.to_lowercase();
-        (
-            format!("SELECT count(block_num) FROM evm_undelegations WHERE delegator='{}'", delegator_lower)
-            ,
-            format!("SELECT tx_id,block_id,block_num,validator,delegator,tm,amount FROM evm_undelegations WHERE delegator='{}' ORDER BY tm DESC LIMIT {} OFFSET {}", delegator_lower, page_size, (page-1)*page_size)
-        )
-    } else {
-        (
-            "SELECT count(block_num) FROM evm_undelegations".to_string()
-            ,
-            format!("SELECT tx_id,block_id,block_num,validator,delegator,tm,amount FROM evm_undelegations ORDER BY tm DESC LIMIT {} OFFSET {}", page_size, (page-1)*page_size))
-    };
-
-    let row = sqlx::query(&sql_total).fetch_one(&mut *pool).await?;
-    let total: i64 = row.try_get("count")?;
+    let page = Page::new(
+        params.page,
+        params.page_size,
+        params.cursor.as_deref(),
+        params.total,
+    )?;
 
-    let mut delegates: Vec<UndelegateResponse> = vec![];
-    let rows = sqlx::query(&sql_query).fetch_all(&mut *pool).await?;
-    for r in rows {
-        let tx_hash: String = r.try_get("tx_id")?;
-        let block_hash: String = r.try_get("block_id")?;
-        let block_num: i64 = r.try_get("block_num")?;
-        let validator: String = r.try_get("validator")?;
-        let delegator: String = r.try_get("delegator")?;
-        let amount: BigDecimal = r.try_get("amount")?;
-        let tm: NaiveDateTime = r.try_get("tm")?;
-
-        delegates.push(UndelegateResponse {
-            tx_hash,
-            block_hash,
-            block_num,
-            validator,
-            delegator,
-            amount: amount.to_string(),
-            timestamp: tm.and_utc().timestamp(),
-        })
-    }
+    let query = delegator_undelegate_query(&params).records(&filter, Some("amount"))?;
 
-    Ok(Json(QueryResult {
-        total,
+    let res = fetch_records(
+        &mut pool,
+        &query,
+        "tx_id,block_id,block_num,validator,delegator,tm,amount,log_index",
+        RECORD_KEY,
         page,
-        page_size,
-        data: delegates,
-    }))
+        |r| {
+            let tx_hash: String = r.try_get("tx_id")?;
+            let block_hash: String = r.try_get("block_id")?;
+            let block_num: i64 = r.try_get("block_num")?;
+            let validator: String = r.try_get("validator")?;
+            let delegator: String = r.try_get("delegator")?;
+            let amount: BigDecimal = r.try_get("amount")?;
+            let tm: NaiveDateTime = r.try_get("tm")?;
+
+            Ok(UndelegateResponse {
+                tx_hash,
+                block_hash,
+                block_num,
+                validator,
+                delegator,
+                amount: amount.to_string(),
+                timestamp: tm.and_utc().timestamp(),
+            })
+        },
+    )
+    .await?;
+
+    Ok(Json(res))
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ValidatorUndelegateRecordsParams {
-    pub validator: Option<String>,
+    pub validator: Option<Address>,
+    pub delegator: Option<Address>,
     pub page: Option<i32>,
     pub page_size: Option<i32>,
+    pub cursor: Option<String>,
+    pub total: Option<Total>,
 }
 
+fn validator_undelegate_query(params: &ValidatorUndelegateRecordsParams) -> ListQuery {
+    ListQuery::new("FROM evm_undelegations")
+        .eq("validator", params.validator.clone())
+        .eq("delegator", params.delegator.clone())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/records/undelegate",
+    tag = "undelegate",
+    params(ValidatorUndelegateRecordsParams, RecordFilter),
+    responses(
+        (status = 200, body = ListResult<Vec<UndelegateResponse>>),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 pub async fn get_validator_undelegate_records(
     State(state): State<Arc<AppState>>,
     params: Query<ValidatorUndelegateRecordsParams>,
-) -> Result<Json<QueryResult<Vec<UndelegateResponse>>>> {
+    filter: Query<RecordFilter>,
+) -> Result<Json<ListResult<Vec<UndelegateResponse>>>> {
     let mut pool = state.pool.acquire().await?;
-    let page = params.page.unwrap_or(1);
-    let page_size = params.page_size.unwrap_or(10);
-
-    let (sql_total, sql_query) = if let Some(validator) = params.0.validator {
-        (
-            format!("SELECT count(block_num) FROM evm_undelegations WHERE validator='{}'", validator)
-            ,
-            format!("SELECT tx_id,block_id,block_num,validator,delegator,tm,amount FROM evm_undelegations WHERE validator='{}' ORDER BY tm DESC LIMIT {} OFFSET {}", validator, page_size, (page-1)*page_size)
-        )
-    } else {
-        (
-            "SELECT count(block_num) FROM evm_undelegations".to_string()
-            ,
-            format!("SELECT tx_id,block_id,block_num,validator,delegator,tm,amount FROM evm_undelegations ORDER BY tm DESC LIMIT {} OFFSET {}", page_size, (page-1)*page_size))
-    };
-
-    let row = sqlx::query(&sql_total).fetch_one(&mut *pool).await?;
+    let page = Page::new(
+        params.page,
+        params.page_size,
+        params.cursor.as_deref(),
+        params.total,
+    )?;
+
+    let query = validator_undelegate_query(&params).records(&filter, Some("amount"))?;
+
+    let res = fetch_records(
+        &mut pool,
+        &query,
+        "tx_id,block_id,block_num,validator,delegator,tm,amount,log_index",
+        RECORD_KEY,
+        page,
+        |r| {
+            let tx_hash: String = r.try_get("tx_id")?;
+            let block_hash: String = r.try_get("block_id")?;
+            let block_num: i64 = r.try_get("block_num")?;
+            let validator: String = r.try_get("validator")?;
+            let delegator: String = r.try_get("delegator")?;
+            let amount: BigDecimal = r.try_get("amount")?;
+            let tm: NaiveDateTime = r.try_get("tm")?;
+
+            Ok(UndelegateResponse {
+                tx_hash,
+                block_hash,
+                block_num,
+                validator,
+                delegator,
+                amount: amount.to_string(),
+                timestamp: tm.and_utc().timestamp(),
+            })
+        },
+    )
+    .await?;
+
+    Ok(Json(res))
+}
+
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PendingUndelegationsParams {
+    pub delegator: Address,
+    /// Released entries (`true`) instead of the still queued ones (default)
+    pub released: Option<bool>,
+    pub page: Option<i32>,
+    pub page_size: Option<i32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/undelegations/pending",
+    tag = "undelegate",
+    params(PendingUndelegationsParams),
+    responses(
+        (status = 200, body = QueryResult<Vec<PendingUndelegationResponse>>),
+        (status = 400, body = ErrorResponse),
+    )
+)]
+pub async fn get_pending_undelegations(
+    State(state): State<Arc<AppState>>,
+    params: Query<PendingUndelegationsParams>,
+) -> Result<Json<QueryResult<Vec<PendingUndelegationResponse>>>> {
+    let mut pool = state.pool.acquire().await?;
+    let (page, page_size) = offset_page(params.page, params.page_size)?;
+    let delegator = params.0.delegator.clone();
+    let released = params.0.released.unwrap_or(false);
+
+    let sql_total = r#"SELECT count(*) FROM evm_undelegation_queue
+        WHERE delegator=$1 AND released=$2"#;
+    let row = sqlx::query(sql_total)
+        .bind(&delegator)
+        .bind(released)
+        .fetch_one(&mut *pool)
+        .await?;
     let total: i64 = row.try_get("count")?;
 
-    let mut delegates: Vec<UndelegateResponse> = vec![];
-    let rows = sqlx::query(&sql_query).fetch_all(&mut *pool).await?;
+    let sql_query = r#"SELECT q.idx,u.tx_id,q.validator,q.delegator,q.amount,q.unlock_time,q.released,q.released_block
+        FROM evm_undelegation_queue q LEFT JOIN LATERAL (
+            SELECT tx_id FROM evm_undelegations
+            WHERE idx=q.idx AND validator=q.validator AND delegator=q.delegator
+            ORDER BY block_num DESC LIMIT 1) u ON true
+        WHERE q.delegator=$1 AND q.released=$2
+        ORDER BY q.idx DESC LIMIT $3 OFFSET $4"#;
+    let rows = sqlx::query(sql_query)
+        .bind(&delegator)
+        .bind(released)
+        .bind(page_size)
+        .bind(rows_before(page, page_size))
+        .fetch_all(&mut *pool)
+        .await?;
+
+    let now = Utc::now().timestamp();
+    let mut undelegations: Vec<PendingUndelegationResponse> = vec![];
     for r in rows {
-        let tx_hash: String = r.try_get("tx_id")?;
-        let block_hash: String = r.try_get("block_id")?;
-        let block_num: i64 = r.try_get("block_num")?;
-        let validator: String = r.try_get("validator")?;
-        let delegator: String = r.try_get("delegator")?;
         let amount: BigDecimal = r.try_get("amount")?;
-        let tm: NaiveDateTime = r.try_get("tm")?;
-
-        delegates.push(UndelegateResponse {
-            tx_hash,
-            block_hash,
-            block_num,
-            validator,
-            delegator,
+        let unlock_time: i64 = r.try_get("unlock_time")?;
+        undelegations.push(PendingUndelegationResponse {
+            index: r.try_get("idx")?,
+            tx_hash: r.try_get("tx_id")?,
+            validator: r.try_get("validator")?,
+            delegator: r.try_get("delegator")?,
             amount: amount.to_string(),
-            timestamp: tm.and_utc().timestamp(),
+            unlock_time,
+            unlock_in: (unlock_time - now).max(0),
+            released: r.try_get("released")?,
+            released_block: r.try_get("released_block")?,
         })
     }
 
@@ -128,6 +237,97 @@ pub async fn get_validator_undelegate_records(
         total,
         page,
         page_size,
-        data: delegates,
+        data: undelegations,
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::tests::assert_filters;
+    use crate::query::Param;
+
+    const FROM: &str = "FROM evm_undelegations";
+    const ADDR: &str = "0x00000000000000000000000000000000000000ab";
+    const OTHER: &str = "0x00000000000000000000000000000000000000cd";
+
+    #[test]
+    fn filter_delegator() {
+        let query = delegator_undelegate_query(&DelegatorUndelegateRecordsParams {
+            delegator: Some(ADDR.parse().unwrap()),
+            ..Default::default()
+        });
+        assert_filters(
+            &query,
+            FROM,
+            &[("delegator", "=", Param::Text(ADDR.into()))],
+        );
+    }
+
+    #[test]
+    fn filter_validator() {
+        let query = validator_undelegate_query(&ValidatorUndelegateRecordsParams {
+            validator: Some(ADDR.parse().unwrap()),
+            ..Default::default()
+        });
+        assert_filters(
+            &query,
+            FROM,
+            &[("validator", "=", Param::Text(ADDR.into()))],
+        );
+    }
+
+    #[test]
+    fn filter_pair() {
+        let query = delegator_undelegate_query(&DelegatorUndelegateRecordsParams {
+            delegator: Some(ADDR.parse().unwrap()),
+            validator: Some(OTHER.parse().unwrap()),
+            ..Default::default()
+        });
+        assert_filters(
+            &query,
+            FROM,
+            &[
+                ("delegator", "=", Param::Text(ADDR.into())),
+                ("validator", "=", Param::Text(OTHER.into())),
+            ],
+        );
+        let query = validator_undelegate_query(&ValidatorUndelegateRecordsParams {
+            validator: Some(OTHER.parse().unwrap()),
+            delegator: Some(ADDR.parse().unwrap()),
+            ..Default::default()
+        });
+        assert_filters(
+            &query,
+            FROM,
+            &[
+                ("validator", "=", Param::Text(OTHER.into())),
+                ("delegator", "=", Param::Text(ADDR.into())),
+            ],
+        );
+    }
+
+    #[test]
+    fn filter_block() {
+        let filter = RecordFilter {
+            from_block: Some(5),
+            to_block: Some(9),
+            ..Default::default()
+        };
+        let query = delegator_undelegate_query(&DelegatorUndelegateRecordsParams {
+            delegator: Some(ADDR.parse().unwrap()),
+            ..Default::default()
+        })
+        .records(&filter, Some("amount"))
+        .unwrap();
+        assert_filters(
+            &query,
+            FROM,
+            &[
+                ("delegator", "=", Param::Text(ADDR.into())),
+                ("block_num", ">=", Param::Int(5)),
+                ("block_num", "<=", Param::Int(9)),
+            ],
+        );
+    }
+}
@@ -1,24 +1,39 @@
 use crate::error::Result;
+use crate::params::{Address, Query};
+use crate::query::{offset_page, rows_before, ListQuery};
 use crate::types::{
-    DelegatorOfValidatorResponse, QueryResult, ValidatorLatest20Response, ValidatorResponse,
-    ValidatorSumRewardResponse, ValidatorVoteResponse,
+    DelegatorOfValidatorResponse, ErrorResponse, QueryResult, ValidatorLatest20Response,
+    ValidatorProfileResponse, ValidatorResponse, ValidatorSumRewardResponse,
+    ValidatorTimelineResponse, ValidatorVoteResponse,
 };
 use crate::AppState;
-use axum::extract::{Query, State};
+use axum::extract::State;
 use axum::Json;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sqlx::types::chrono::NaiveDateTime;
 use sqlx::types::BigDecimal;
 use sqlx::Row;
-use std::ops::{Add, Sub};
+use std::ops::Sub;
 use std::sync::Arc;
+use utoipa::IntoParams;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ValidatorSumRewardParams {
-    pub validator: String,
+    pub validator: Address,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/reward/sum",
+    tag = "validators",
+    params(ValidatorSumRewardParams),
+    responses(
+        (status = 200, body = ValidatorSumRewardResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 pub async fn get_validator_sum_reward(
     State(state): State<Arc<AppState>>,
     params: Query<ValidatorSumRewardParams>,
@@ -37,42 +52,215 @@ pub async fn get_validator_sum_reward(
     }))
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct GetVoteParams {
-    pub validator: String,
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ValidatorHistoryParams {
+    pub validator: Address,
     pub page: Option<i32>,
     pub page_size: Option<i32>,
 }
 
-const BLOCKS_PER_DAY: i64 = 24 * 60 * 60 / 15; // 5760
-
-pub async fn get_validator_votes(
+#[utoipa::path(
+    get,
+    path = "/api/validator/history",
+    tag = "validators",
+    params(ValidatorHistoryParams),
+    responses(
+        (status = 200, body = QueryResult<Vec<ValidatorProfileResponse>>),
+        (status = 400, body = ErrorResponse),
+    )
+)]
+pub async fn get_validator_history(
     State(state): State<Arc<AppState>>,
-    params: Query<GetVoteParams>,
-) -> Result<Json<QueryResult<Vec<ValidatorVoteResponse>>>> {
+    params: Query<ValidatorHistoryParams>,
+) -> Result<Json<QueryResult<Vec<ValidatorProfileResponse>>>> {
     let mut pool = state.pool.acquire().await?;
-    let page = params.page.unwrap_or(1);
-    let page_size = params.page_size.unwrap_or(10);
+    let (page, page_size) = offset_page(params.page, params.page_size)?;
 
-    let sql_total = r#"SELECT count(block_num) FROM evm_validators WHERE validator = $1
-        AND block_num >= (SELECT max(block_num) FROM evm_validators) - $2"#;
+    let sql_total = r#"SELECT count(*) FROM evm_validator_profiles WHERE validator=$1"#;
     let row = sqlx::query(sql_total)
         .bind(&params.0.validator)
-        .bind(BLOCKS_PER_DAY)
         .fetch_one(&mut *pool)
         .await?;
     let total: i64 = row.try_get("count")?;
 
-    let sql_query = r#"SELECT block_num,should_vote,voted FROM evm_validators WHERE
-        validator = $1 AND block_num >= (SELECT max(block_num) FROM evm_validators) - $2
-        ORDER BY block_num DESC LIMIT $3 OFFSET $4"#;
+    let sql_query = r#"SELECT tx_id,block_num,tm,memo,rate,op FROM evm_validator_profiles
+        WHERE validator=$1 ORDER BY block_num DESC,op DESC LIMIT $2 OFFSET $3"#;
     let rows = sqlx::query(sql_query)
         .bind(&params.0.validator)
-        .bind(BLOCKS_PER_DAY)
         .bind(page_size)
-        .bind((page - 1) * page_size)
+        .bind(rows_before(page, page_size))
         .fetch_all(&mut *pool)
         .await?;
+    let mut profiles: Vec<ValidatorProfileResponse> = vec![];
+    for r in rows {
+        let tx_id: String = r.try_get("tx_id")?;
+        let block_num: i64 = r.try_get("block_num")?;
+        let tm: NaiveDateTime = r.try_get("tm")?;
+        let memo: Value = r.try_get("memo")?;
+        let rate: BigDecimal = r.try_get("rate")?;
+        let op: i32 = r.try_get("op")?;
+        profiles.push(ValidatorProfileResponse {
+            tx_id,
+            block_num,
+            timestamp: tm.and_utc().timestamp(),
+            memo,
+            rate: rate.to_string(),
+            op,
+        })
+    }
+
+    Ok(Json(QueryResult {
+        total,
+        page,
+        page_size,
+        data: profiles,
+    }))
+}
+
+/// Transitions of a validator and the stake, jail and punish events concerning it, $1 is the validator.
+const SQL_VALIDATOR_TIMELINE: &str = "SELECT block_num,tm,'transition' source,kind,NULL::varchar tx_id,\
+    jsonb_build_object('old',old_value,'new',new_value) detail FROM evm_validator_events WHERE validator=$1 \
+    UNION ALL SELECT block_num,tm,'event','stake',tx_id,\
+    jsonb_build_object('staker',staker,'amount',amount::text,'rate',rate::text) FROM evm_stakes WHERE validator=$1 \
+    UNION ALL SELECT block_num,tm,'event',CASE WHEN jailed THEN 'jail' ELSE 'unjail' END,tx_id,\
+    jsonb_build_object('jailed',jailed) FROM evm_jailed WHERE validator=$1 \
+    UNION ALL SELECT block_num,tm,'event','punish',tx_id,\
+    jsonb_build_object('unvoted',unvoted @> jsonb_build_array($1::text),'byzantine',byztine @> jsonb_build_array($1::text)) \
+    FROM evm_punish WHERE unvoted @> jsonb_build_array($1::text) OR byztine @> jsonb_build_array($1::text)";
+
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ValidatorTimelineParams {
+    pub validator: Address,
+    pub page: Option<i32>,
+    pub page_size: Option<i32>,
+}
+
+/// Lifecycle of a validator, newest first.
+#[utoipa::path(
+    get,
+    path = "/api/validator/timeline",
+    tag = "validators",
+    params(ValidatorTimelineParams),
+    responses(
+        (status = 200, body = QueryResult<Vec<ValidatorTimelineResponse>>),
+        (status = 400, body = ErrorResponse),
+    )
+)]
+pub async fn get_validator_timeline(
+    State(state): State<Arc<AppState>>,
+    params: Query<ValidatorTimelineParams>,
+) -> Result<Json<QueryResult<Vec<ValidatorTimelineResponse>>>> {
+    let mut pool = state.pool.acquire().await?;
+    let (page, page_size) = offset_page(params.page, params.page_size)?;
+    let validator = params.0.validator.clone();
+
+    let sql_total = format!("SELECT count(*) FROM ({}) t", SQL_VALIDATOR_TIMELINE);
+    let row = sqlx::query(&sql_total)
+        .bind(&validator)
+        .fetch_one(&mut *pool)
+        .await?;
+    let total: i64 = row.try_get("count")?;
+
+    let sql_query = format!(
+        "SELECT * FROM ({}) t ORDER BY block_num DESC,source,kind LIMIT $2 OFFSET $3",
+        SQL_VALIDATOR_TIMELINE
+    );
+    let rows = sqlx::query(&sql_query)
+        .bind(&validator)
+        .bind(page_size)
+        .bind(rows_before(page, page_size))
+        .fetch_all(&mut *pool)
+        .await?;
+    let mut timeline: Vec<ValidatorTimelineResponse> = vec![];
+    for r in rows {
+        let tm: NaiveDateTime = r.try_get("tm")?;
+        timeline.push(ValidatorTimelineResponse {
+            block_num: r.try_get("block_num")?,
+            timestamp: tm.and_utc().timestamp(),
+            source: r.try_get("source")?,
+            kind: r.try_get("kind")?,
+            tx_id: r.try_get("tx_id")?,
+            detail: r.try_get("detail")?,
+        })
+    }
+
+    Ok(Json(QueryResult {
+        total,
+        page,
+        page_size,
+        data: timeline,
+    }))
+}
+
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GetVoteParams {
+    pub validator: Address,
+    pub page: Option<i32>,
+    pub page_size: Option<i32>,
+}
+
+const BLOCKS_PER_DAY: i64 = 24 * 60 * 60 / 15; // 5760
+
+#[utoipa::path(
+    get,
+    path = "/api/diff/vote",
+    tag = "validators",
+    params(GetVoteParams),
+    responses(
+        (status = 200, body = QueryResult<Vec<ValidatorVoteResponse>>),
+        (status = 400, body = ErrorResponse),
+    )
+)]
+pub async fn get_validator_votes(
+    State(state): State<Arc<AppState>>,
+    params: Query<GetVoteParams>,
+) -> Result<Json<QueryResult<Vec<ValidatorVoteResponse>>>> {
+    let mut pool = state.pool.acquire().await?;
+    let (page, page_size) = offset_page(params.page, params.page_size)?;
+
+    // The series covers the last `BLOCKS_PER_DAY` blocks up to the last block the validator
+    // was checked at, from its first complete snapshot on.
+    let sql_range = r#"SELECT hb.block_num AS last,
+        (SELECT min(block_num) FROM evm_validators WHERE validator = $1 AND pubkey <> '') AS first
+        FROM evm_validator_heartbeat hb WHERE hb.validator = $1"#;
+    let range = sqlx::query(sql_range)
+        .bind(&params.0.validator)
+        .fetch_optional(&mut *pool)
+        .await?;
+    let (last, first) = match range {
+        Some(r) => (
+            r.try_get::<i64, _>("last")?,
+            r.try_get::<Option<i64>, _>("first")?,
+        ),
+        None => (0, None),
+    };
+    let lower = first.map(|f| f.max(last - BLOCKS_PER_DAY));
+    let total = lower.map(|l| (last - l + 1).max(0)).unwrap_or(0);
+
+    // Snapshots are only stored on change, each block of the page takes the latest snapshot
+    // at or before it.
+    let offset = rows_before(page, page_size);
+    let top = last - offset;
+    let bottom = lower.map(|l| l.max(top - page_size as i64 + 1));
+    let rows = match bottom {
+        Some(bottom) if top >= bottom => {
+            let sql_query = r#"SELECT b.block_num,ev.should_vote,ev.voted
+                FROM generate_series($2::bigint, $3::bigint, -1) b(block_num)
+                JOIN LATERAL (SELECT should_vote,voted FROM evm_validators WHERE validator = $1
+                AND block_num <= b.block_num AND pubkey <> '' ORDER BY block_num DESC LIMIT 1) ev
+                ON true ORDER BY b.block_num DESC"#;
+            sqlx::query(sql_query)
+                .bind(&params.0.validator)
+                .bind(top)
+                .bind(bottom)
+                .fetch_all(&mut *pool)
+                .await?
+        }
+        _ => vec![],
+    };
     let mut votes: Vec<ValidatorVoteResponse> = vec![];
     for r in rows {
         let block_num: i64 = r.try_get("block_num")?;
@@ -93,35 +281,60 @@ pub async fn get_validator_votes(
     }))
 }
 
-#[derive(Serialize, Deserialize)]
+/// Latest snapshot of every validator seen in the last update cycle.
+/// Rows with an empty pubkey are partial snapshots written by older updaters when a contract
+/// read failed, they are skipped in favour of the latest complete snapshot.
+const SQL_CURRENT_VALIDATORS: &str = "FROM evm_validator_heartbeat hb \
+    JOIN LATERAL (SELECT * FROM evm_validators WHERE validator=hb.validator AND pubkey<>'' ORDER BY block_num DESC LIMIT 1) ev ON true \
+    JOIN evm_validator_current_profile vp ON vp.validator=hb.validator \
+    WHERE hb.block_num=(SELECT max(block_num) FROM evm_validator_heartbeat) ";
+
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct GetValidatorsParams {
-    pub validator: Option<String>,
+    pub validator: Option<Address>,
     pub online: Option<bool>,
     pub offline: Option<bool>,
     pub page: Option<i32>,
     pub page_size: Option<i32>,
 }
 
+fn validators_query(params: &GetValidatorsParams) -> ListQuery {
+    ListQuery::new_filtered(SQL_CURRENT_VALIDATORS)
+        .eq("ev.active", params.online)
+        .eq("ev.jailed", params.offline)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/validators",
+    tag = "validators",
+    params(GetValidatorsParams),
+    responses(
+        (status = 200, body = QueryResult<Vec<ValidatorResponse>>),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 pub async fn get_validators(
     State(state): State<Arc<AppState>>,
     params: Query<GetValidatorsParams>,
 ) -> Result<Json<QueryResult<Vec<ValidatorResponse>>>> {
     let mut pool = state.pool.acquire().await?;
-    let page = params.page.unwrap_or(1);
-    let page_size = params.page_size.unwrap_or(10);
+    let (page, page_size) = offset_page(params.page, params.page_size)?;
 
     let mut validators: Vec<ValidatorResponse> = vec![];
-    let mut total: i64 = 0;
+    let total: i64;
     if params.0.validator.is_some() {
-        let sql_query_memo = r#"SELECT memo FROM evm_stakes WHERE validator=$1"#;
-        let row = sqlx::query(&sql_query_memo)
+        let sql_query_memo = r#"SELECT memo FROM evm_validator_current_profile WHERE validator=$1"#;
+        let row = sqlx::query(sql_query_memo)
             .bind(&params.0.validator)
             .fetch_one(&mut *pool)
             .await?;
         let memo: Value = row.try_get("memo")?;
 
-        let sql_latest = r#"SELECT max(block_num) mb FROM evm_validators WHERE validator=$1"#;
-        let row = sqlx::query(&sql_latest)
+        let sql_latest =
+            r#"SELECT max(block_num) mb FROM evm_validators WHERE validator=$1 AND pubkey<>''"#;
+        let row = sqlx::query(sql_latest)
             .bind(&params.0.validator)
             .fetch_one(&mut *pool)
             .await?;
@@ -129,7 +342,7 @@ pub async fn get_validators(
 
         let sql_validator = r#"SELECT validator,pubkey,pubkey_type,rate,staker,power,unbound,punish_rate,
             begin_block,active,jailed,unjail_time,should_vote,voted FROM evm_validators WHERE block_num=$1 AND validator=$2"#;
-        let r = sqlx::query(&sql_validator)
+        let r = sqlx::query(sql_validator)
             .bind(max_block)
             .bind(&params.0.validator)
             .fetch_one(&mut *pool)
@@ -168,44 +381,21 @@ pub async fn get_validators(
             memo,
         })
     } else {
-        let mut sql_total = "SELECT count(ev.block_num) cnt,max(ev.block_num) mb FROM evm_validators ev WHERE ev.block_num=(SELECT max(block_num) FROM evm_validators) ".to_string();
-        let mut query_params: Vec<String> = vec![];
-        if let Some(ref validator) = params.0.validator {
-            query_params.push(format!("ev.validator='{}' ", validator));
-        }
-        if let Some(online) = params.0.online {
-            query_params.push(format!("ev.active={} ", online))
-        }
-        if let Some(offline) = params.0.offline {
-            query_params.push(format!("ev.jailed={} ", offline))
-        }
-        if !query_params.is_empty() {
-            sql_total = sql_total
-                .add("AND ")
-                .add(query_params.join("AND ").as_str());
-        }
-        let row = sqlx::query(&sql_total).fetch_one(&mut *pool).await?;
-        total = row.try_get("cnt")?;
-        let max_block: i64 = row.try_get("mb")?;
-
-        let mut sql_query = format!("SELECT ev.validator,ev.pubkey,ev.pubkey_type,ev.rate,ev.staker,ev.power,\
-        ev.unbound,ev.punish_rate,ev.begin_block,ev.active,ev.jailed,ev.unjail_time,ev.should_vote,ev.voted,es.memo \
-        FROM evm_stakes es LEFT JOIN evm_validators ev ON es.validator=ev.validator WHERE ev.block_num={} ", max_block);
-        if !query_params.is_empty() {
-            sql_query = sql_query
-                .add("AND ")
-                .add(query_params.join("AND ").as_str());
-        }
-        sql_query = sql_query.add(
-            format!(
-                "ORDER BY power DESC LIMIT {} OFFSET {}",
+        let query = validators_query(&params);
+        let row = query.count().build().fetch_one(&mut *pool).await?;
+        total = row.try_get("count")?;
+
+        let rows = query
+            .select(
+                "ev.validator,ev.pubkey,ev.pubkey_type,ev.rate,ev.staker,ev.power,ev.unbound,\
+                ev.punish_rate,ev.begin_block,ev.active,ev.jailed,ev.unjail_time,ev.should_vote,ev.voted,vp.memo",
+                "power DESC",
+                page,
                 page_size,
-                (page - 1) * page_size
             )
-            .as_str(),
-        );
-
-        let rows = sqlx::query(&sql_query).fetch_all(&mut *pool).await?;
+            .build()
+            .fetch_all(&mut *pool)
+            .await?;
         for r in rows {
             let validator: String = r.try_get("validator")?;
             let staker: String = r.try_get("staker")?;
@@ -250,11 +440,22 @@ pub async fn get_validators(
     }))
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct GetLatest20Params {
-    pub validator: String,
+    pub validator: Address,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/diff/latest",
+    tag = "validators",
+    params(GetLatest20Params),
+    responses(
+        (status = 200, body = Vec<ValidatorLatest20Response>),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 pub async fn get_latest20(
     State(state): State<Arc<AppState>>,
     params: Query<GetLatest20Params>,
@@ -267,7 +468,7 @@ pub async fn get_latest20(
         .bind(&params.0.validator)
         .fetch_all(&mut *pool)
         .await?;
-    if rows.len() == 0 {
+    if rows.is_empty() {
         return Ok(Json(latest));
     };
 
@@ -279,11 +480,11 @@ pub async fn get_latest20(
         .fetch_one(&mut *pool)
         .await?;
     let mut sum: BigDecimal = row.try_get("sum")?;
-    for i in 0..rows.len() {
-        let block_num: i64 = rows[i].try_get("block_num")?;
-        let amount: BigDecimal = rows[i].try_get("amount")?;
-        let delegator: String = rows[i].try_get("delegator")?;
-        let op: i32 = rows[i].try_get("op")?;
+    for r in rows {
+        let block_num: i64 = r.try_get("block_num")?;
+        let amount: BigDecimal = r.try_get("amount")?;
+        let delegator: String = r.try_get("delegator")?;
+        let op: i32 = r.try_get("op")?;
 
         latest.push(ValidatorLatest20Response {
             block_num,
@@ -299,20 +500,30 @@ pub async fn get_latest20(
     Ok(Json(latest))
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct DelegatorsOfValidatorParams {
-    pub validator: String,
+    pub validator: Address,
     pub page: Option<i32>,
     pub page_size: Option<i32>,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/validator/delegators",
+    tag = "validators",
+    params(DelegatorsOfValidatorParams),
+    responses(
+        (status = 200, body = QueryResult<Vec<DelegatorOfValidatorResponse>>),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 pub async fn get_delegators_of_validator(
     State(state): State<Arc<AppState>>,
     params: Query<DelegatorsOfValidatorParams>,
 ) -> Result<Json<QueryResult<Vec<DelegatorOfValidatorResponse>>>> {
     let mut pool = state.pool.acquire().await?;
-    let page = params.page.unwrap_or(1);
-    let page_size = params.page_size.unwrap_or(10);
+    let (page, page_size) = offset_page(params.page, params.page_size)?;
 
     let sql_total = r#"SELECT count(distinct delegator) FROM evm_audit WHERE validator=$1"#;
     let sql_query = r#"SELECT DISTINCT delegator,sum(amount),dense_rank() over (order by sum(amount) desc) rank
@@ -325,7 +536,7 @@ pub async fn get_delegators_of_validator(
     let rows = sqlx::query(sql_query)
         .bind(&params.0.validator)
         .bind(page_size)
-        .bind((page - 1) * page_size)
+        .bind(rows_before(page, page_size))
         .fetch_all(&mut *pool)
         .await?;
 
@@ -350,20 +561,30 @@ pub async fn get_delegators_of_validator(
     }))
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ValidatorOfDelegatorParams {
-    pub delegator: String,
+    pub delegator: Address,
     pub page: Option<i32>,
     pub page_size: Option<i32>,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/delegator/validators",
+    tag = "validators",
+    params(ValidatorOfDelegatorParams),
+    responses(
+        (status = 200, body = QueryResult<Vec<String>>),
+        (status = 400, body = ErrorResponse),
+    )
+)]
 pub async fn get_validators_of_delegator(
     State(state): State<Arc<AppState>>,
     params: Query<ValidatorOfDelegatorParams>,
 ) -> Result<Json<QueryResult<Vec<String>>>> {
     let mut pool = state.pool.acquire().await?;
-    let page = params.page.unwrap_or(1);
-    let page_size = params.page_size.unwrap_or(10);
+    let (page, page_size) = offset_page(params.page, params.page_size)?;
 
     let sql_total = r#"SELECT count(distinct validator) FROM evm_audit WHERE delegator=$1"#;
     let row = sqlx::query(sql_total)
@@ -377,7 +598,7 @@ pub async fn get_validators_of_delegator(
     let rows = sqlx::query(sql_query)
         .bind(&params.0.delegator)
         .bind(page_size)
-        .bind((page - 1) * page_size)
+        .bind(rows_before(page, page_size))
         .fetch_all(&mut *pool)
         .await?;
     let mut validators: Vec<String> = vec![];
@@ -393,3 +614,39 @@ pub async fn get_validators_of_delegator(
         data: validators,
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::Param;
+
+    fn params() -> GetValidatorsParams {
+        GetValidatorsParams {
+            validator: None,
+            online: None,
+            offline: None,
+            page: None,
+            page_size: None,
+        }
+    }
+
+    #[test]
+    fn filter_online() {
+        let query = validators_query(&GetValidatorsParams {
+            online: Some(true),
+            ..params()
+        });
+        assert!(query.count().sql().ends_with("AND ev.active=$1"));
+        assert_eq!(query.filters()[0].param, Param::Bool(true));
+    }
+
+    #[test]
+    fn filter_offline() {
+        let query = validators_query(&GetValidatorsParams {
+            offline: Some(false),
+            ..params()
+        });
+        assert!(query.count().sql().ends_with("AND ev.jailed=$1"));
+        assert_eq!(query.filters()[0].param, Param::Bool(false));
+    }
+}
@@ -33,7 +33,7 @@ create table if not exists evm_stakes(
     rate numeric(48) not null,
     primary key(tx_id,validator,staker)
 );
-create index idx_stk_blknum on evm_stakes(block_num);
+create index if not exists idx_stk_blknum on evm_stakes(block_num);
 
 create table if not exists evm_delegations(
     tx_id varchar(66) not null,
@@ -45,7 +45,7 @@ create table if not exists evm_delegations(
     amount numeric(48) not null,
     primary key(tx_id,validator,delegator)
 );
-create index idx_dlg_blknum on evm_delegations(block_num);
+create index if not exists idx_dlg_blknum on evm_delegations(block_num);
 
 create table if not exists evm_undelegations(
     tx_id varchar(66) not null,
@@ -60,7 +60,7 @@ create table if not exists evm_undelegations(
     op_type integer not null,
     primary key(tx_id,validator,delegator)
 );
-create index idx_udlg_blknum on evm_undelegations(block_num);
+create index if not exists idx_udlg_blknum on evm_undelegations(block_num);
 
 create table if not exists evm_jailed(
     tx_id varchar(66) not null,
@@ -105,7 +105,7 @@ create table if not exists evm_coinbase_mint(
     amount numeric(48) not null,
     primary key(tx_id,validator,delegator)
 );
-create index idx_mnt_blknum on evm_coinbase_mint(block_num);
+create index if not exists idx_mnt_blknum on evm_coinbase_mint(block_num);
 
 create table if not exists evm_receipts(
     tx_id varchar(66) not null,
@@ -118,10 +118,10 @@ create table if not exists evm_receipts(
     primary key(tx_id)
 );
 
-create index idxerbid on evm_receipts(block_id);
-create index idxerbn on evm_receipts(block_num);
-create index idxerfrom on evm_receipts(from_addr);
-create index idxerto on evm_receipts(to_addr);
+create index if not exists idxerbid on evm_receipts(block_id);
+create index if not exists idxerbn on evm_receipts(block_num);
+create index if not exists idxerfrom on evm_receipts(from_addr);
+create index if not exists idxerto on evm_receipts(to_addr);
 
 create table if not exists evm_audit(
     tx_id varchar(66) not null,
@@ -131,8 +131,8 @@ create table if not exists evm_audit(
     amount numeric(48) not null,
     op integer not null
 );
-create index idxvld on evm_audit(validator);
-create index idxdlg on evm_audit(delegator);
+create index if not exists idxvld on evm_audit(validator);
+create index if not exists idxdlg on evm_audit(delegator);
 
 create table if not exists evm_validators(
     block_num bigint not null,
@@ -153,14 +153,14 @@ create table if not exists evm_validators(
     primary key (block_num,validator,staker)
 ) partition by hash(block_num);
 
-create index idxevbn on evm_validators(block_num);
-create index idxevvld on evm_validators(validator);
+create index if not exists idxevbn on evm_validators(block_num);
+create index if not exists idxevvld on evm_validators(validator);
 
-create table evm_validators_0 partition of evm_validators for values with (MODULUS 5, REMAINDER 0);
-create table evm_validators_1 partition of evm_validators for values with (MODULUS 5, REMAINDER 1);
-create table evm_validators_2 partition of evm_validators for values with (MODULUS 5, REMAINDER 2);
-create table evm_validators_3 partition of evm_validators for values with (MODULUS 5, REMAINDER 3);
-create table evm_validators_4 partition of evm_validators for values with (MODULUS 5, REMAINDER 4);
+create table if not exists evm_validators_0 partition of evm_validators for values with (MODULUS 5, REMAINDER 0);
+create table if not exists evm_validators_1 partition of evm_validators for values with (MODULUS 5, REMAINDER 1);
+create table if not exists evm_validators_2 partition of evm_validators for values with (MODULUS 5, REMAINDER 2);
+create table if not exists evm_validators_3 partition of evm_validators for values with (MODULUS 5, REMAINDER 3);
+create table if not exists evm_validators_4 partition of evm_validators for values with (MODULUS 5, REMAINDER 4);
 
 create or replace function put_delegate() returns trigger as $$
     begin
@@ -169,6 +169,7 @@ create or replace function put_delegate() returns trigger as $$
     end;
 $$ language plpgsql;
 
+drop trigger if exists delegate_trigger on evm_delegations;
 create trigger delegate_trigger
     after insert or update
     on evm_delegations
@@ -181,6 +182,7 @@ create or replace function put_undelegate() returns trigger as $$
     end;
 $$ language plpgsql;
 
+drop trigger if exists undelegate_trigger on evm_undelegations;
 create trigger undelegate_trigger
     after insert or update
     on evm_undelegations
@@ -193,9 +195,8 @@ create or replace function update_validator() returns trigger as $$
     end;
 $$ language plpgsql;
 
+drop trigger if exists update_validator_trigger on evm_update_validator;
 create trigger update_validator_trigger
     after insert or update
     on evm_update_validator
     for each row execute procedure update_validator();
-
-
@@ -0,0 +1,41 @@
+-- op: 0 - Stake, 1 - UpdateValidator
+create table if not exists evm_validator_profiles(
+    tx_id varchar(66) not null,
+    block_num bigint not null,
+    tm timestamp not null,
+    validator varchar(66) not null,
+    memo jsonb not null,
+    rate numeric(48) not null,
+    op integer not null,
+    primary key(tx_id,validator)
+);
+create index if not exists idx_vp_vld on evm_validator_profiles(validator,block_num);
+
+create or replace view evm_validator_current_profile as
+    select distinct on (validator) validator,memo,rate,block_num,tm from evm_validator_profiles
+    order by validator,block_num desc,op desc;
+
+create or replace function put_stake_profile() returns trigger as $$
+    begin
+        insert into evm_validator_profiles select new.tx_id,new.block_num,new.tm,new.validator,new.memo,new.rate,0
+            on conflict(tx_id,validator) do update set block_num=excluded.block_num,tm=excluded.tm,memo=excluded.memo,rate=excluded.rate;
+        return null;
+    end;
+$$ language plpgsql;
+
+drop trigger if exists stake_profile_trigger on evm_stakes;
+create trigger stake_profile_trigger
+    after insert or update
+    on evm_stakes
+    for each row execute procedure put_stake_profile();
+
+create or replace function update_validator() returns trigger as $$
+    begin
+        insert into evm_validator_profiles select new.tx_id,new.block_num,new.tm,new.validator,new.memo,new.rate,1
+            on conflict(tx_id,validator) do update set block_num=excluded.block_num,tm=excluded.tm,memo=excluded.memo,rate=excluded.rate;
+        return null;
+    end;
+$$ language plpgsql;
+
+insert into evm_validator_profiles select tx_id,block_num,tm,validator,memo,rate,0 from evm_stakes on conflict do nothing;
+insert into evm_validator_profiles select tx_id,block_num,tm,validator,memo,rate,1 from evm_update_validator on conflict do nothing;
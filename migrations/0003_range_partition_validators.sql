@@ -0,0 +1,38 @@
+-- evm_validators moves from 5 fixed hash partitions to range partitions by block_num.
+-- Existing rows stay in evm_validators_legacy, attached as the range below the current tip.
+-- Newer ranges are created by the updater ahead of the tip.
+alter table evm_validators rename to evm_validators_legacy;
+alter index evm_validators_pkey rename to evm_validators_legacy_pkey;
+alter index idxevbn rename to idxevbn_legacy;
+alter index idxevvld rename to idxevvld_legacy;
+
+create table evm_validators(
+    block_num bigint not null,
+    validator varchar(66) not null,
+    pubkey varchar(66) not null,
+    pubkey_type integer not null,
+    rate numeric(48) not null,
+    staker varchar(66) not null,
+    power numeric(48) not null,
+    unbound numeric(48) not null,
+    punish_rate numeric(48) not null,
+    begin_block bigint not null,
+    active boolean not null,
+    jailed boolean not null,
+    unjail_time timestamp not null,
+    should_vote integer not null,
+    voted integer not null,
+    primary key (block_num,validator,staker)
+) partition by range(block_num);
+
+do $$
+    declare
+        upper_bound bigint;
+    begin
+        select coalesce(max(block_num),0)+1 into upper_bound from evm_validators_legacy;
+        execute format('alter table evm_validators attach partition evm_validators_legacy for values from (minvalue) to (%s)', upper_bound);
+    end;
+$$ language plpgsql;
+
+create index idxevbn on evm_validators(block_num);
+create index idxevvld on evm_validators(validator);
@@ -0,0 +1,13 @@
+-- evm_validators only gets a row when a validator changes, the heartbeat records when
+-- each validator was last checked by the updater.
+create table if not exists evm_validator_heartbeat(
+    validator varchar(66) not null,
+    block_num bigint not null,
+    checked_at timestamp not null,
+    primary key(validator)
+);
+
+create index if not exists idxevvldbn on evm_validators(validator,block_num);
+
+insert into evm_validator_heartbeat select validator,max(block_num),now() from evm_validators group by validator
+    on conflict do nothing;
@@ -0,0 +1,19 @@
+-- Bound and unbound amounts of each delegator-validator pair known from evm_audit,
+-- snapshotted by the updater. A row is only written when the position changed.
+create table if not exists evm_delegator_positions(
+    block_num bigint not null,
+    tm timestamp not null,
+    validator varchar(66) not null,
+    delegator varchar(66) not null,
+    bound numeric(48) not null,
+    unbound numeric(48) not null,
+    primary key(validator,delegator,block_num)
+);
+create index if not exists idxdpdlg on evm_delegator_positions(delegator,block_num);
+
+-- Completed snapshot runs, a position is current as of the latest run.
+create table if not exists evm_delegator_position_runs(
+    block_num bigint not null,
+    tm timestamp not null,
+    primary key(block_num)
+);
@@ -0,0 +1,20 @@
+-- The Staking contract keeps undelegations in a queue, entries in [undelegationsStart,undelegationsEnd)
+-- are pending and entries below undelegationsStart have been released to the delegator.
+-- The updater reconciles the queue into this table.
+create table if not exists evm_undelegation_queue(
+    idx bigint not null,
+    validator varchar(66) not null,
+    delegator varchar(66) not null,
+    amount numeric(48) not null,
+    unlock_time bigint not null,
+    released boolean not null default false,
+    released_block bigint,
+    primary key(idx)
+);
+create index if not exists idxuqdlg on evm_undelegation_queue(delegator,idx);
+
+create index if not exists idx_udlg_idx on evm_undelegations(idx);
+
+insert into evm_undelegation_queue(idx,validator,delegator,amount,unlock_time)
+    select idx,validator,delegator,amount,unlock_time from evm_undelegations
+    on conflict do nothing;
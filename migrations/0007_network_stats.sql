@@ -0,0 +1,13 @@
+-- Network-wide staking figures recorded by the updater.
+create table if not exists evm_network_stats(
+    block_num bigint not null,
+    tm timestamp not null,
+    total_delegation numeric(48) not null,
+    global_pre_issue numeric(48) not null,
+    return_rate numeric(48) not null,
+    active_validators integer not null,
+    inactive_validators integer not null,
+    total_power numeric(48) not null,
+    primary key(block_num)
+);
+create index if not exists idxnstm on evm_network_stats(tm);
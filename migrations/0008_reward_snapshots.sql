@@ -0,0 +1,14 @@
+-- Reward accounting of each validator, snapshotted by the updater together with delegator positions.
+-- commission_unclaimed is what the validator earned from its delegators and has not claimed yet,
+-- delegators_unclaimed is the sum of what its delegators earned and have not claimed yet.
+create table if not exists evm_reward_snapshots(
+    block_num bigint not null,
+    tm timestamp not null,
+    validator varchar(66) not null,
+    staker_reward_ratio numeric(48) not null,
+    delegator_reward_ratio numeric(48) not null,
+    commission_unclaimed numeric(48) not null,
+    delegators_unclaimed numeric(48) not null,
+    delegators integer not null,
+    primary key(validator,block_num)
+);
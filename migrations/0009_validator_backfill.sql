@@ -0,0 +1,7 @@
+-- Blocks whose validators were replayed by `updater backfill`, a re-run skips them.
+create table if not exists evm_validator_backfill(
+    block_num bigint not null,
+    validators integer not null,
+    tm timestamp not null,
+    primary key(block_num)
+);
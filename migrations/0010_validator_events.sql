@@ -0,0 +1,15 @@
+-- Transitions between consecutive validator snapshots, derived by the updater.
+-- kind is one of active, inactive, jailed, unjailed, power, rate, punish_rate.
+create table if not exists evm_validator_events(
+    block_num bigint not null,
+    tm timestamp not null,
+    validator varchar(66) not null,
+    kind varchar(16) not null,
+    old_value varchar(66) not null,
+    new_value varchar(66) not null,
+    primary key(validator,block_num,kind)
+);
+
+create index if not exists idxjldvld on evm_jailed(validator);
+create index if not exists idxpununv on evm_punish using gin(unvoted jsonb_path_ops);
+create index if not exists idxpunbyz on evm_punish using gin(byztine jsonb_path_ops);
@@ -0,0 +1,33 @@
+-- Position of the event log in its block, the last part of the (block_num, tx_id, log_index)
+-- cursor of the record endpoints. Rows scanned before this column existed get a per-tx
+-- sequence instead; rescanning their blocks replaces it with the real log index.
+alter table evm_stakes add column if not exists log_index integer not null default 0;
+alter table evm_delegations add column if not exists log_index integer not null default 0;
+alter table evm_undelegations add column if not exists log_index integer not null default 0;
+alter table evm_coinbase_mint add column if not exists log_index integer not null default 0;
+
+update evm_stakes t set log_index=s.seq from (
+    select tx_id,validator,staker,row_number() over (partition by tx_id order by validator,staker)-1 seq
+    from evm_stakes
+) s where t.tx_id=s.tx_id and t.validator=s.validator and t.staker=s.staker and s.seq>0;
+
+update evm_delegations t set log_index=s.seq from (
+    select tx_id,validator,delegator,row_number() over (partition by tx_id order by validator,delegator)-1 seq
+    from evm_delegations
+) s where t.tx_id=s.tx_id and t.validator=s.validator and t.delegator=s.delegator and s.seq>0;
+
+update evm_undelegations t set log_index=s.seq from (
+    select tx_id,validator,delegator,row_number() over (partition by tx_id order by validator,delegator)-1 seq
+    from evm_undelegations
+) s where t.tx_id=s.tx_id and t.validator=s.validator and t.delegator=s.delegator and s.seq>0;
+
+update evm_coinbase_mint t set log_index=s.seq from (
+    select tx_id,validator,delegator,row_number() over (partition by tx_id order by validator,delegator)-1 seq
+    from evm_coinbase_mint
+) s where t.tx_id=s.tx_id and t.validator=s.validator and t.delegator=s.delegator and s.seq>0;
+
+create index if not exists idx_stk_cursor on evm_stakes(block_num,tx_id,log_index);
+create index if not exists idx_dlg_cursor on evm_delegations(block_num,tx_id,log_index);
+create index if not exists idx_udlg_cursor on evm_undelegations(block_num,tx_id,log_index);
+create index if not exists idx_mnt_cursor on evm_coinbase_mint(block_num,tx_id,log_index);
+create index if not exists idx_rcpt_cursor on evm_receipts(block_num,tx_id);
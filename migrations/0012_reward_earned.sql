@@ -0,0 +1,23 @@
+-- Unclaimed balances drop to zero on claim, so the reward split is audited on what was earned.
+-- delegators_earned is delegators_unclaimed plus the CoinbaseMint amounts claimed from the validator.
+-- commission_earned adds up every increase of commission_unclaimed, a drop counts as a claim.
+alter table evm_reward_snapshots add column if not exists commission_earned numeric(48) not null default 0;
+alter table evm_reward_snapshots add column if not exists delegators_earned numeric(48) not null default 0;
+
+update evm_reward_snapshots t set commission_earned=s.earned from (
+    select validator,block_num,sum(
+        case when prev is null then commission_unclaimed
+            when commission_unclaimed>=prev then commission_unclaimed-prev
+            else commission_unclaimed end
+    ) over (partition by validator order by block_num) earned
+    from (
+        select validator,block_num,commission_unclaimed,
+            lag(commission_unclaimed) over (partition by validator order by block_num) prev
+        from evm_reward_snapshots
+    ) r
+) s where t.validator=s.validator and t.block_num=s.block_num;
+
+update evm_reward_snapshots t set delegators_earned=t.delegators_unclaimed+coalesce((
+    select sum(m.amount) from evm_coinbase_mint m
+    where m.validator=t.validator and m.block_num<=t.block_num
+), 0);
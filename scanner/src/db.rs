@@ -5,11 +5,20 @@ use sqlx::types::chrono::NaiveDateTime;
 use sqlx::types::BigDecimal;
 use sqlx::{PgPool, Row};
 
+/// Channel notified with the validators touched by the staking events of a block.
+pub const VALIDATOR_CHANNEL: &str = "evm_validator_changed";
+/// Channel notified with the validator/delegator pairs whose delegation or rewards the events
+/// of a block changed.
+pub const DELEGATOR_CHANNEL: &str = "evm_delegator_changed";
+// A notification payload must stay under 8000 bytes.
+const NOTIFY_BATCH: usize = 100;
+const NOTIFY_PAIR_BATCH: usize = 50;
+
 #[derive(Debug)]
 pub struct Storage {
     pool: PgPool,
 }
-#[allow(dead_code)]
+#[allow(clippy::too_many_arguments)]
 impl Storage {
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
@@ -24,38 +33,6 @@ impl Storage {
         Ok(height as u64)
     }
 
-    pub async fn upsert_block(
-        &self,
-        block_id: &str,
-        block_num: i64,
-        tm: NaiveDateTime,
-    ) -> Result<()> {
-        sqlx::query("insert into evm_blocks values($1,$2,$3) on conflict(block_id) do update set block_num=$2,tm=$3")
-            .bind(block_id)
-            .bind(block_num)
-            .bind(tm)
-            .execute(&self.pool)
-            .await?;
-        Ok(())
-    }
-
-    pub async fn upsert_tx(
-        &self,
-        tx_id: &str,
-        block_id: &str,
-        block_num: i64,
-        tm: NaiveDateTime,
-    ) -> Result<()> {
-        sqlx::query("insert into evm_txs values($1,$2,$3,$4) on conflict(tx_id) do update set block_id=$2,block_num=$3,tm=$4")
-            .bind(tx_id)
-            .bind(block_id)
-            .bind(block_num)
-            .bind(tm)
-            .execute(&self.pool)
-            .await?;
-        Ok(())
-    }
-
     pub async fn upsert_tip(&self, height: i64) -> Result<()> {
         sqlx::query(
             "insert into evm_last_height values($1,$2) on conflict(tip) do update set height=$2",
@@ -81,9 +58,10 @@ impl Storage {
         amount: BigDecimal,
         memo: Value,
         rate: BigDecimal,
+        log_index: i32,
     ) -> Result<()> {
-        sqlx::query("INSERT INTO evm_stakes VALUES($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11) ON \
-            CONFLICT(tx_id,validator,staker) DO UPDATE SET block_id=$2,block_num=$3,tm=$4,pubkey=$6,ty=$7,amount=$9,memo=$10,rate=$11"
+        sqlx::query("INSERT INTO evm_stakes VALUES($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12) ON \
+            CONFLICT(tx_id,validator,staker) DO UPDATE SET block_id=$2,block_num=$3,tm=$4,pubkey=$6,ty=$7,amount=$9,memo=$10,rate=$11,log_index=$12"
         )
             .bind(tx_id)
             .bind(block_id)
@@ -96,6 +74,7 @@ impl Storage {
             .bind(amount)
             .bind(memo)
             .bind(rate)
+            .bind(log_index)
             .execute(&self.pool)
             .await?;
 
@@ -111,10 +90,11 @@ impl Storage {
         validator: &str,
         delegator: &str,
         amount: BigDecimal,
+        log_index: i32,
     ) -> Result<()> {
         sqlx::query(
-            "INSERT INTO evm_delegations VALUES($1,$2,$3,$4,$5,$6,$7) ON \
-                CONFLICT(tx_id,validator,delegator) DO UPDATE SET block_id=$2,block_num=$3,tm=$4,amount=$7",
+            "INSERT INTO evm_delegations VALUES($1,$2,$3,$4,$5,$6,$7,$8) ON \
+                CONFLICT(tx_id,validator,delegator) DO UPDATE SET block_id=$2,block_num=$3,tm=$4,amount=$7,log_index=$8",
         )
             .bind(tx_id)
             .bind(block_id)
@@ -123,6 +103,7 @@ impl Storage {
             .bind(validator)
             .bind(delegator)
             .bind(amount)
+            .bind(log_index)
             .execute(&self.pool)
             .await?;
 
@@ -141,9 +122,10 @@ impl Storage {
         unlock_time: i64,
         amount: BigDecimal,
         op_type: i32,
+        log_index: i32,
     ) -> Result<()> {
-        sqlx::query("INSERT INTO evm_undelegations VALUES($1,$2,$3,$4,$5,$6,$7,$8,$9,$10) ON \
-            CONFLICT(tx_id,validator,delegator) DO UPDATE SET block_id=$2,block_num=$3,tm=$4,idx=$5,unlock_time=$8,amount=$9,op_type=$10"
+        sqlx::query("INSERT INTO evm_undelegations VALUES($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11) ON \
+            CONFLICT(tx_id,validator,delegator) DO UPDATE SET block_id=$2,block_num=$3,tm=$4,idx=$5,unlock_time=$8,amount=$9,op_type=$10,log_index=$11"
         )
             .bind(tx_id)
             .bind(block_id)
@@ -155,6 +137,7 @@ impl Storage {
             .bind(unlock_time)
             .bind(amount)
             .bind(op_type)
+            .bind(log_index)
             .execute(&self.pool)
             .await?;
 
@@ -249,10 +232,11 @@ impl Storage {
         delegator: &str,
         pubkey: &str,
         amount: BigDecimal,
+        log_index: i32,
     ) -> Result<()> {
         sqlx::query(
-            "INSERT INTO evm_coinbase_mint VALUES($1,$2,$3,$4,$5,$6,$7,$8) ON \
-                CONFLICT(tx_id,validator,delegator) DO UPDATE SET block_id=$2,block_num=$3,tm=$4,pubkey=$7,amount=$8",
+            "INSERT INTO evm_coinbase_mint VALUES($1,$2,$3,$4,$5,$6,$7,$8,$9) ON \
+                CONFLICT(tx_id,validator,delegator) DO UPDATE SET block_id=$2,block_num=$3,tm=$4,pubkey=$7,amount=$8,log_index=$9",
         )
             .bind(tx_id)
             .bind(block_id)
@@ -262,6 +246,7 @@ impl Storage {
             .bind(delegator)
             .bind(pubkey)
             .bind(amount)
+            .bind(log_index)
             .execute(&self.pool)
             .await?;
 
@@ -293,4 +278,37 @@ impl Storage {
         .await?;
         Ok(())
     }
+
+    /// Publishes `validators` on `VALIDATOR_CHANNEL` as `{"block_num":..,"validators":[..]}`.
+    pub async fn notify_validators(&self, block_num: i64, validators: &[String]) -> Result<()> {
+        for chunk in validators.chunks(NOTIFY_BATCH) {
+            let payload = serde_json::json!({
+                "block_num": block_num,
+                "validators": chunk,
+            });
+            sqlx::query("SELECT pg_notify($1,$2)")
+                .bind(VALIDATOR_CHANNEL)
+                .bind(payload.to_string())
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Publishes `(validator, delegator)` pairs on `DELEGATOR_CHANNEL` as
+    /// `{"block_num":..,"pairs":[[validator,delegator],..]}`.
+    pub async fn notify_pairs(&self, block_num: i64, pairs: &[(String, String)]) -> Result<()> {
+        for chunk in pairs.chunks(NOTIFY_PAIR_BATCH) {
+            let payload = serde_json::json!({
+                "block_num": block_num,
+                "pairs": chunk,
+            });
+            sqlx::query("SELECT pg_notify($1,$2)")
+                .bind(DELEGATOR_CHANNEL)
+                .bind(payload.to_string())
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
 }
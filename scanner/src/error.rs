@@ -5,9 +5,9 @@ use std::num::ParseIntError;
 pub enum ScannerError {
     Custom(String),
     DBError(sqlx::Error),
+    MigrateError(sqlx::migrate::MigrateError),
     JoinError(tokio::task::JoinError),
     ParseUrlError(url::ParseError),
-    ReqwestError(reqwest::Error),
     ParseIntError(ParseIntError),
     DecodeError(base64::DecodeError),
     SerdeJsonError(serde_json::Error),
@@ -17,6 +17,25 @@ pub enum ScannerError {
     EtherProviderError(ethers::providers::ProviderError),
 }
 
+impl std::fmt::Display for ScannerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScannerError::Custom(e) => write!(f, "{}", e),
+            ScannerError::DBError(e) => write!(f, "db: {}", e),
+            ScannerError::MigrateError(e) => write!(f, "migrate: {}", e),
+            ScannerError::JoinError(e) => write!(f, "join: {}", e),
+            ScannerError::ParseUrlError(e) => write!(f, "url: {}", e),
+            ScannerError::ParseIntError(e) => write!(f, "int: {}", e),
+            ScannerError::DecodeError(e) => write!(f, "base64: {}", e),
+            ScannerError::SerdeJsonError(e) => write!(f, "json: {}", e),
+            ScannerError::EthAbiError(e) => write!(f, "abi: {}", e),
+            ScannerError::BlockNotFound(n) => write!(f, "block {} not found", n),
+            ScannerError::HexError(e) => write!(f, "hex: {}", e),
+            ScannerError::EtherProviderError(e) => write!(f, "provider: {}", e),
+        }
+    }
+}
+
 impl From<ProviderError> for ScannerError {
     fn from(e: ProviderError) -> Self {
         ScannerError::EtherProviderError(e)
@@ -35,6 +54,12 @@ impl From<sqlx::Error> for ScannerError {
     }
 }
 
+impl From<sqlx::migrate::MigrateError> for ScannerError {
+    fn from(e: sqlx::migrate::MigrateError) -> Self {
+        ScannerError::MigrateError(e)
+    }
+}
+
 impl From<tokio::task::JoinError> for ScannerError {
     fn from(e: tokio::task::JoinError) -> Self {
         ScannerError::JoinError(e)
@@ -47,12 +72,6 @@ impl From<url::ParseError> for ScannerError {
     }
 }
 
-impl From<reqwest::Error> for ScannerError {
-    fn from(e: reqwest::Error) -> Self {
-        ScannerError::ReqwestError(e)
-    }
-}
-
 impl From<ParseIntError> for ScannerError {
     fn from(e: ParseIntError) -> Self {
         ScannerError::ParseIntError(e)
@@ -1,14 +1,13 @@
 mod db;
 mod error;
 mod scanner;
-mod types;
 
 extern crate core;
 extern crate num_cpus;
 
 use crate::db::Storage;
 use crate::scanner::Scanner;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use error::Result;
 use ethers::providers::Provider;
 use log::{error, info};
@@ -19,13 +18,15 @@ use std::env;
 use std::time::Duration;
 
 const DEFAULT_INTERVAL: u64 = 4; // 4s
-const DEFAULT_RPC_RETRIES: usize = 3;
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
     /// Node RPC
-    #[arg(long)]
-    pub node: String,
+    #[arg(long, required = true)]
+    pub node: Option<String>,
     /// Pull single block
     #[arg(long)]
     pub single: bool,
@@ -37,9 +38,16 @@ struct Args {
     pub interval: Option<u64>,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Apply pending schema migrations and exit
+    Migrate,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
+    let args = Args::parse();
 
     let db_url = env::var("DATABASE_URL").expect("Not find `DATABASE_URL`");
     let pool: Pool<Postgres> = PoolOptions::new()
@@ -48,8 +56,14 @@ async fn main() -> Result<()> {
         .expect("connect db failed");
 
     info!("Connecting db...ok");
+    if let Some(Command::Migrate) = args.command {
+        schema::run_migrations(&pool).await?;
+        return Ok(());
+    }
+    schema::check_schema_version(&pool, &["scanner"]).await?;
+
     let storage = Storage::new(pool);
-    let args = Args::parse();
+    let node = args.node.expect("`--node` is required");
     let start = if let Some(start) = args.start {
         start
     } else {
@@ -60,15 +74,14 @@ async fn main() -> Result<()> {
     } else {
         Duration::from_secs(DEFAULT_INTERVAL)
     };
-    info!("Node RPC: {}", args.node);
+    info!("Node RPC: {}", node);
     info!("Scanning interval: {}s", interval.as_secs());
     info!("Starting from block: {}", start);
 
-    let provider = Provider::try_from(args.node)?;
+    let provider = Provider::try_from(node)?;
 
     info!("Starting syncing...");
-    let scanner = Scanner::new(DEFAULT_RPC_RETRIES, num_cpus::get(), provider, storage)
-        .expect("failed to new scanner");
+    let scanner = Scanner::new(num_cpus::get(), provider, storage).expect("failed to new scanner");
     let _ = scanner.run(start, interval, args.single).await;
 
     Ok(())
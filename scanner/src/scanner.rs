@@ -3,18 +3,18 @@ use crate::error::Result;
 use crate::error::ScannerError;
 use crossbeam::channel::bounded;
 use ethers::contract::{parse_log, EthEvent};
-use ethers::prelude::{Middleware, TransactionReceipt};
+use ethers::prelude::Middleware;
 use ethers::providers::{Http, Provider};
+use ethers::types::Address;
+use ethers::types::Bytes;
 use ethers::types::U256;
-use ethers::types::{Address, Block};
-use ethers::types::{Bytes, TxHash};
 use ethers::utils::hex::encode_prefixed;
 use log::{debug, error, info};
-use reqwest::{Client, ClientBuilder, Url};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sqlx::types::chrono::DateTime;
 use sqlx::types::BigDecimal;
+use std::collections::BTreeSet;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
@@ -41,69 +41,7 @@ const EVENT_UPDATE_VALIDATOR_TOPIC: &str =
 const EVENT_COINBASE_MINT_TOPIC: &str =
     "0xb2cf206b70e745484dd39dc6b8e6166ce07246bd00baa4bd059f15733b2130e9";
 
-#[allow(dead_code)]
-pub struct FindoraRPC {
-    pub url: Url,
-    pub client: Client,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct EthRpcRequest<T> {
-    pub id: i32,
-    pub jsonrpc: String,
-    pub method: String,
-    pub params: T,
-}
-
-#[allow(dead_code)]
-impl<T> EthRpcRequest<T> {
-    pub fn body(method: &str, params: T) -> Self {
-        EthRpcRequest {
-            id: 1,
-            jsonrpc: "2.0".into(),
-            method: method.to_string(),
-            params,
-        }
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct GetBlockByNumberResponse<T> {
-    pub jsonrpc: String,
-    pub id: i32,
-    pub result: Option<T>,
-}
-
-#[allow(dead_code)]
-impl FindoraRPC {
-    pub fn new(timeout: Duration, url: Url) -> Self {
-        let client = ClientBuilder::new().timeout(timeout).build().unwrap();
-        FindoraRPC { url, client }
-    }
-
-    pub async fn get_block_by_number(&self, block_num: u64) -> Result<Option<Block<TxHash>>> {
-        let params = (format!("0x{:x}", block_num), false);
-        let req = EthRpcRequest::body("eth_getBlockByNumber", params);
-        let resp = self.client.post(self.url.clone()).json(&req).send().await?;
-        let block = resp
-            .json::<GetBlockByNumberResponse<Block<TxHash>>>()
-            .await?;
-        Ok(block.result)
-    }
-
-    pub async fn get_transaction_receipt(&self, tx: TxHash) -> Result<Option<TransactionReceipt>> {
-        let params = vec![tx];
-        let req = EthRpcRequest::body("eth_getTransactionReceipt", params);
-        let resp = self.client.post(self.url.clone()).json(&req).send().await?;
-        let receipt = resp
-            .json::<GetBlockByNumberResponse<TransactionReceipt>>()
-            .await?;
-        Ok(receipt.result)
-    }
-}
-
 pub struct RpcCaller {
-    pub retries: usize,
     pub provider: Provider<Http>,
     pub storage: Storage,
     pub threads: usize,
@@ -205,6 +143,10 @@ impl RpcCaller {
         let tm = DateTime::from_timestamp(block.timestamp.as_u64() as i64, 0)
             .unwrap()
             .naive_utc();
+        // Validators whose staking state the events of this block may have changed.
+        let mut touched: BTreeSet<Address> = BTreeSet::new();
+        // Validator/delegator pairs whose bound amounts or rewards changed, for cache eviction.
+        let mut pairs: BTreeSet<(Address, Address)> = BTreeSet::new();
 
         for tx_hash in block.transactions {
             debug!("Syncing tx receipt: {:?}", tx_hash);
@@ -228,10 +170,12 @@ impl RpcCaller {
                 )
                 .await?;
             for log in receipt.logs {
+                let log_index = log.log_index.unwrap_or_default().as_u32() as i32;
                 match encode_prefixed(log.topics[0].as_bytes()).as_str() {
                     EVENT_STAKE_TOPIC => {
                         debug!("Stake:{:?}", encode_prefixed(log.topics[0]).to_string());
                         let stake: EventStake = parse_log(log.clone())?;
+                        touched.insert(stake.validator);
                         let mem_val: Value = serde_json::from_str(&stake.memo)?;
                         self.storage
                             .upsert_stake(
@@ -248,12 +192,15 @@ impl RpcCaller {
                                 mem_val,
                                 BigDecimal::from_str(&stake.rate.as_u128().to_string())
                                     .unwrap_or_default(),
+                                log_index,
                             )
                             .await?
                     }
                     EVENT_DELEGATION_TOPIC => {
                         debug!("Delegate: {:?}", encode_prefixed(log.topics[0]).to_string());
                         let delegation: EventDelegation = parse_log(log.clone())?;
+                        touched.insert(delegation.validator);
+                        pairs.insert((delegation.validator, delegation.delegator));
                         self.storage
                             .upsert_delegation(
                                 &tx_id,
@@ -264,6 +211,7 @@ impl RpcCaller {
                                 &encode_prefixed(delegation.delegator.as_bytes()),
                                 BigDecimal::from_str(&delegation.amount.as_u128().to_string())
                                     .unwrap_or_default(),
+                                log_index,
                             )
                             .await?
                     }
@@ -273,6 +221,8 @@ impl RpcCaller {
                             encode_prefixed(log.topics[0]).to_string()
                         );
                         let undelegation: EventUndelegation = parse_log(log.clone())?;
+                        touched.insert(undelegation.validator);
+                        pairs.insert((undelegation.validator, undelegation.delegator));
                         self.storage
                             .upsert_undelegation(
                                 &tx_id,
@@ -286,6 +236,7 @@ impl RpcCaller {
                                 BigDecimal::from_str(&undelegation.amount.as_u128().to_string())
                                     .unwrap_or_default(),
                                 undelegation.operation_type as i32,
+                                log_index,
                             )
                             .await?
                     }
@@ -295,6 +246,7 @@ impl RpcCaller {
                             encode_prefixed(log.topics[0]).to_string()
                         );
                         let coinbase_mint: EventCoinbaseMint = parse_log(log.clone())?;
+                        pairs.insert((coinbase_mint.validator, coinbase_mint.delegator));
                         self.storage
                             .upsert_coinbase_mint(
                                 &tx_id,
@@ -306,12 +258,14 @@ impl RpcCaller {
                                 &coinbase_mint.public_key.to_string(),
                                 BigDecimal::from_str(&coinbase_mint.amount.as_u128().to_string())
                                     .unwrap_or_default(),
+                                log_index,
                             )
                             .await?
                     }
                     EVENT_JAILED_TOPIC => {
                         debug!("[Jailed] {:?}", encode_prefixed(log.topics[0]).to_string());
                         let jailed: EventJailed = parse_log(log.clone())?;
+                        touched.insert(jailed.validator);
                         self.storage
                             .upsert_jailed(
                                 &tx_id,
@@ -329,7 +283,9 @@ impl RpcCaller {
                             encode_prefixed(log.topics[0]).to_string()
                         );
                         let update_validator: EventUpdateValidator = parse_log(log.clone())?;
-                        let memo_val = serde_json::to_value(update_validator.memo)?;
+                        touched.insert(update_validator.validator);
+                        let memo_val: Value = serde_json::from_str(&update_validator.memo)
+                            .unwrap_or(Value::String(update_validator.memo));
 
                         self.storage
                             .upsert_update_validator(
@@ -347,6 +303,9 @@ impl RpcCaller {
                     EVENT_PUNISH_TOPIC => {
                         debug!("[Punish] {:?}", encode_prefixed(log.topics[0]).to_string());
                         let punish: EventPunish = parse_log(log.clone())?;
+                        // Only validators that missed the vote can get punished or jailed.
+                        touched.extend(punish.unvoted.iter());
+                        touched.extend(punish.byztine.iter());
                         let voted_val = serde_json::to_value(punish.voted)?;
                         let unvoted_val = serde_json::to_value(punish.unvoted)?;
                         let byzantine_val = serde_json::to_value(punish.byztine)?;
@@ -367,6 +326,23 @@ impl RpcCaller {
             }
         }
 
+        if !touched.is_empty() {
+            let validators = touched
+                .iter()
+                .map(|v| encode_prefixed(v.as_bytes()))
+                .collect::<Vec<String>>();
+            self.storage
+                .notify_validators(height as i64, &validators)
+                .await?;
+        }
+        if !pairs.is_empty() {
+            let pairs = pairs
+                .iter()
+                .map(|(v, d)| (encode_prefixed(v.as_bytes()), encode_prefixed(d.as_bytes())))
+                .collect::<Vec<(String, String)>>();
+            self.storage.notify_pairs(height as i64, &pairs).await?;
+        }
+
         Ok(())
     }
 }
@@ -376,14 +352,8 @@ pub struct Scanner {
 }
 
 impl Scanner {
-    pub fn new(
-        retries: usize,
-        threads: usize,
-        provider: Provider<Http>,
-        storage: Storage,
-    ) -> Result<Self> {
+    pub fn new(threads: usize, provider: Provider<Http>, storage: Storage) -> Result<Self> {
         let caller = RpcCaller {
-            retries,
             provider,
             storage,
             threads,
@@ -0,0 +1,80 @@
+//! Schema migrations embedded in every binary, and the version check they run at startup.
+use log::info;
+use sqlx::migrate::{MigrateError, Migrator};
+use sqlx::{PgPool, Row};
+
+pub static MIGRATOR: Migrator = sqlx::migrate!("../migrations");
+
+pub async fn run_migrations(pool: &PgPool) -> Result<(), MigrateError> {
+    MIGRATOR.run(pool).await?;
+    info!("Schema migrated to version {}", latest_version());
+    Ok(())
+}
+
+/// Refuses to run unless the database has exactly the migrations embedded in this binary.
+/// `migrate_with` names the binaries whose `migrate` command applies them.
+pub async fn check_schema_version(pool: &PgPool, migrate_with: &[&str]) -> Result<(), String> {
+    let rows = sqlx::query("SELECT version FROM _sqlx_migrations WHERE success ORDER BY version")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| {
+            format!(
+                "schema is not initialized, run {} first: {}",
+                migrate_hint(migrate_with),
+                e
+            )
+        })?;
+    let applied = rows
+        .iter()
+        .map(|r| r.try_get("version"))
+        .collect::<Result<Vec<i64>, sqlx::Error>>()
+        .map_err(|e| e.to_string())?;
+
+    if let Some(v) = applied
+        .iter()
+        .find(|v| !MIGRATOR.iter().any(|m| m.version == **v))
+    {
+        return Err(format!(
+            "unknown schema version {} in database, this binary supports up to {}",
+            v,
+            latest_version()
+        ));
+    }
+    if let Some(m) = MIGRATOR.iter().find(|m| !applied.contains(&m.version)) {
+        return Err(format!(
+            "schema version {} ({}) is not applied, run {} first",
+            m.version,
+            m.description,
+            migrate_hint(migrate_with)
+        ));
+    }
+
+    info!("Schema version: {}", latest_version());
+    Ok(())
+}
+
+fn latest_version() -> i64 {
+    MIGRATOR.iter().map(|m| m.version).max().unwrap_or_default()
+}
+
+fn migrate_hint(binaries: &[&str]) -> String {
+    binaries
+        .iter()
+        .map(|b| format!("`{} migrate`", b))
+        .collect::<Vec<String>>()
+        .join(" or ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hint_names_binaries() {
+        assert_eq!(migrate_hint(&["scanner"]), "`scanner migrate`");
+        assert_eq!(
+            migrate_hint(&["scanner", "updater"]),
+            "`scanner migrate` or `updater migrate`"
+        );
+    }
+}
@@ -0,0 +1,4 @@
+// Recompile when migrations change, they are embedded with `sqlx::migrate!`.
+fn main() {
+    println!("cargo:rerun-if-changed=../migrations");
+}
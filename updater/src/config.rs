@@ -0,0 +1,424 @@
+use crate::error::{Result, UpdaterError};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Read;
+use std::str::FromStr;
+use url::Url;
+
+pub const DEFAULT_CONFIG_PATH: &str = "./config.toml";
+const DEFAULT_RPC_RETRIES: usize = 3;
+const DEFAULT_INTERVAL: u64 = 14; // 14s
+const DEFAULT_PARTITION_BLOCKS: u64 = 100_000; // about 17 days
+const DEFAULT_PARTITIONS_AHEAD: u64 = 1;
+const MAX_PARTITIONS_AHEAD: u64 = 100;
+const DEFAULT_MULTICALL_BATCH: usize = 200;
+const MULTICALL3_ADDRESS: &str = "0xcA11bde05977b3631167028862bE2a173976CA11";
+const DEFAULT_POSITIONS_INTERVAL: u64 = 3600;
+const DEFAULT_STATS_INTERVAL: u64 = 600;
+const DEFAULT_POWER_THRESHOLD: f64 = 1.0; // 1%
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdaterConfig {
+    pub evm_rpc: String,
+    pub staking: String,
+    pub reward: String,
+    pub db_url: String,
+    /// Seconds between two validator updates
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// Extra attempts of a failed contract read
+    #[serde(default = "default_retries")]
+    pub retries: usize,
+    #[serde(default)]
+    pub partition: PartitionConfig,
+    #[serde(default)]
+    pub multicall: MulticallConfig,
+    #[serde(default)]
+    pub positions: PositionConfig,
+    #[serde(default)]
+    pub stats: StatsConfig,
+    #[serde(default)]
+    pub events: EventConfig,
+}
+
+/// Batches contract reads through a deployed Multicall3 contract.
+/// Reads fall back to one call each when it is disabled or not deployed.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MulticallConfig {
+    pub enabled: bool,
+    pub address: String,
+    /// Maximum number of reads aggregated into one call
+    pub batch: usize,
+}
+
+impl Default for MulticallConfig {
+    fn default() -> Self {
+        MulticallConfig {
+            enabled: true,
+            address: MULTICALL3_ADDRESS.to_string(),
+            batch: DEFAULT_MULTICALL_BATCH,
+        }
+    }
+}
+
+fn default_interval() -> u64 {
+    DEFAULT_INTERVAL
+}
+
+fn default_retries() -> usize {
+    DEFAULT_RPC_RETRIES
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RetentionMode {
+    Detach,
+    Drop,
+}
+
+impl FromStr for RetentionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "detach" => Ok(RetentionMode::Detach),
+            "drop" => Ok(RetentionMode::Drop),
+            _ => Err(format!("unknown mode {}, expected detach or drop", s)),
+        }
+    }
+}
+
+/// Range partitioning of `evm_validators` by block number.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PartitionConfig {
+    /// Number of blocks covered by one partition
+    pub blocks: u64,
+    /// Number of partitions created ahead of the tip
+    pub ahead: u64,
+    /// Partitions whose last block is older than this are detached or dropped
+    pub retention_days: Option<u64>,
+    pub retention_mode: RetentionMode,
+}
+
+impl Default for PartitionConfig {
+    fn default() -> Self {
+        PartitionConfig {
+            blocks: DEFAULT_PARTITION_BLOCKS,
+            ahead: DEFAULT_PARTITIONS_AHEAD,
+            retention_days: None,
+            retention_mode: RetentionMode::Detach,
+        }
+    }
+}
+
+/// Snapshots of delegator positions and reward accounting.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PositionConfig {
+    /// Seconds between two snapshots
+    pub interval: u64,
+}
+
+impl Default for PositionConfig {
+    fn default() -> Self {
+        PositionConfig {
+            interval: DEFAULT_POSITIONS_INTERVAL,
+        }
+    }
+}
+
+/// Network-wide statistics, recorded after a validator update.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct StatsConfig {
+    /// Minimum seconds between two records
+    pub interval: u64,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        StatsConfig {
+            interval: DEFAULT_STATS_INTERVAL,
+        }
+    }
+}
+
+/// Transitions between consecutive validator snapshots.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct EventConfig {
+    /// Minimum power change, in percent of the previous power, recorded as a transition
+    pub power_threshold: f64,
+}
+
+impl Default for EventConfig {
+    fn default() -> Self {
+        EventConfig {
+            power_threshold: DEFAULT_POWER_THRESHOLD,
+        }
+    }
+}
+
+impl UpdaterConfig {
+    /// Loads the config file at `file_path`, then applies `UPDATER_*` environment variables.
+    pub fn load(file_path: &str) -> Result<Self> {
+        let mut f = File::open(file_path)
+            .map_err(|e| format!("can't open config file {}: {}", file_path, e))?;
+        let mut s = String::new();
+        f.read_to_string(&mut s)?;
+        let mut c: UpdaterConfig =
+            toml::from_str(&s).map_err(|e| format!("invalid config file {}: {}", file_path, e))?;
+        c.apply_env(env_var)?;
+        Ok(c)
+    }
+
+    /// Overrides settings with the variables `var` finds, nested keys are joined by `_`,
+    /// e.g. `UPDATER_MULTICALL_ADDRESS` for `multicall.address`.
+    fn apply_env(&mut self, var: impl Fn(&str) -> Result<Option<String>>) -> Result<()> {
+        if let Some(v) = var("UPDATER_EVM_RPC")? {
+            self.evm_rpc = v;
+        }
+        if let Some(v) = var("UPDATER_STAKING")? {
+            self.staking = v;
+        }
+        if let Some(v) = var("UPDATER_REWARD")? {
+            self.reward = v;
+        }
+        if let Some(v) = var("UPDATER_DB_URL")? {
+            self.db_url = v;
+        }
+        if let Some(v) = var("UPDATER_INTERVAL")? {
+            self.interval = parse_setting("UPDATER_INTERVAL", &v)?;
+        }
+        if let Some(v) = var("UPDATER_RETRIES")? {
+            self.retries = parse_setting("UPDATER_RETRIES", &v)?;
+        }
+        if let Some(v) = var("UPDATER_PARTITION_BLOCKS")? {
+            self.partition.blocks = parse_setting("UPDATER_PARTITION_BLOCKS", &v)?;
+        }
+        if let Some(v) = var("UPDATER_PARTITION_AHEAD")? {
+            self.partition.ahead = parse_setting("UPDATER_PARTITION_AHEAD", &v)?;
+        }
+        if let Some(v) = var("UPDATER_PARTITION_RETENTION_DAYS")? {
+            self.partition.retention_days =
+                Some(parse_setting("UPDATER_PARTITION_RETENTION_DAYS", &v)?);
+        }
+        if let Some(v) = var("UPDATER_PARTITION_RETENTION_MODE")? {
+            self.partition.retention_mode = parse_setting("UPDATER_PARTITION_RETENTION_MODE", &v)?;
+        }
+        if let Some(v) = var("UPDATER_MULTICALL_ENABLED")? {
+            self.multicall.enabled = parse_setting("UPDATER_MULTICALL_ENABLED", &v)?;
+        }
+        if let Some(v) = var("UPDATER_MULTICALL_ADDRESS")? {
+            self.multicall.address = v;
+        }
+        if let Some(v) = var("UPDATER_MULTICALL_BATCH")? {
+            self.multicall.batch = parse_setting("UPDATER_MULTICALL_BATCH", &v)?;
+        }
+        if let Some(v) = var("UPDATER_POSITIONS_INTERVAL")? {
+            self.positions.interval = parse_setting("UPDATER_POSITIONS_INTERVAL", &v)?;
+        }
+        if let Some(v) = var("UPDATER_STATS_INTERVAL")? {
+            self.stats.interval = parse_setting("UPDATER_STATS_INTERVAL", &v)?;
+        }
+        if let Some(v) = var("UPDATER_EVENTS_POWER_THRESHOLD")? {
+            self.events.power_threshold = parse_setting("UPDATER_EVENTS_POWER_THRESHOLD", &v)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the command line flags, which override the file and the environment.
+    pub fn apply_flags(&mut self, node: Option<&str>, interval: Option<u64>) {
+        if let Some(node) = node {
+            self.evm_rpc = node.to_string();
+        }
+        if let Some(interval) = interval {
+            self.interval = interval;
+        }
+    }
+
+    /// Checks every setting, the error names the first invalid one.
+    pub fn validate(&self) -> Result<()> {
+        Url::parse(&self.evm_rpc).map_err(|e| invalid("evm_rpc", e))?;
+        Address::from_str(&self.staking).map_err(|e| invalid("staking", e))?;
+        Address::from_str(&self.reward).map_err(|e| invalid("reward", e))?;
+        let db_url = Url::parse(&self.db_url).map_err(|e| invalid("db_url", e))?;
+        if !matches!(db_url.scheme(), "postgres" | "postgresql") {
+            return Err(invalid("db_url", "must be a postgres:// URL"));
+        }
+        if self.interval == 0 {
+            return Err(invalid("interval", "must be greater than 0"));
+        }
+        if self.partition.blocks == 0 {
+            return Err(invalid("partition.blocks", "must be greater than 0"));
+        }
+        if self.partition.ahead > MAX_PARTITIONS_AHEAD {
+            return Err(invalid(
+                "partition.ahead",
+                format!("must be at most {}", MAX_PARTITIONS_AHEAD),
+            ));
+        }
+        if self.partition.retention_days == Some(0) {
+            return Err(invalid(
+                "partition.retention_days",
+                "must be greater than 0",
+            ));
+        }
+        if self.multicall.enabled {
+            let multicall = &self.multicall;
+            Address::from_str(&multicall.address).map_err(|e| invalid("multicall.address", e))?;
+            if multicall.batch < 2 {
+                return Err(invalid("multicall.batch", "must be at least 2"));
+            }
+        }
+        if !(self.events.power_threshold >= 0.0 && self.events.power_threshold.is_finite()) {
+            return Err(invalid(
+                "events.power_threshold",
+                "must be a non-negative number",
+            ));
+        }
+        if self.positions.interval == 0 {
+            return Err(invalid("positions.interval", "must be greater than 0"));
+        }
+        if self.stats.interval == 0 {
+            return Err(invalid("stats.interval", "must be greater than 0"));
+        }
+        Ok(())
+    }
+}
+
+fn env_var(name: &str) -> Result<Option<String>> {
+    match std::env::var(name) {
+        Ok(v) => Ok(Some(v)),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(invalid(name, e)),
+    }
+}
+
+fn parse_setting<T: FromStr>(name: &str, value: &str) -> Result<T>
+where
+    T::Err: Display,
+{
+    value.parse().map_err(|e| invalid(name, e))
+}
+
+fn invalid(setting: &str, reason: impl Display) -> UpdaterError {
+    UpdaterError::InvalidSetting(setting.to_string(), reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const CONFIG: &str = r#"
+        evm_rpc = "https://rpc-mainnet.findora.org/"
+        staking = "0x7a598dEf738a01D771fF92Be33064D5c5E0BC12C"
+        reward = "0xEDA79C4dA47E9b27820Ef244aa2af7a50657e443"
+        db_url = "postgres://postgres@localhost/postgres"
+        interval = 14
+
+        [partition]
+        blocks = 1000
+    "#;
+
+    fn config(vars: &[(&str, &str)]) -> Result<UpdaterConfig> {
+        let vars = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        let mut c: UpdaterConfig = toml::from_str(CONFIG).unwrap();
+        c.apply_env(|name| Ok(vars.get(name).cloned()))?;
+        Ok(c)
+    }
+
+    fn invalid_setting(r: Result<()>) -> String {
+        match r {
+            Err(UpdaterError::InvalidSetting(setting, _)) => setting,
+            r => panic!("expected an invalid setting, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn env_then_flags_override_file() {
+        let mut c = config(&[
+            ("UPDATER_EVM_RPC", "http://env:8545"),
+            ("UPDATER_INTERVAL", "20"),
+            ("UPDATER_PARTITION_RETENTION_DAYS", "30"),
+            ("UPDATER_PARTITION_RETENTION_MODE", "drop"),
+            ("UPDATER_MULTICALL_BATCH", "50"),
+            ("UPDATER_STATS_INTERVAL", "60"),
+        ])
+        .unwrap();
+        assert_eq!(c.evm_rpc, "http://env:8545");
+        assert_eq!(c.interval, 20);
+        assert_eq!(c.staking, "0x7a598dEf738a01D771fF92Be33064D5c5E0BC12C");
+        assert_eq!(c.partition.blocks, 1000);
+        assert_eq!(c.partition.retention_days, Some(30));
+        assert_eq!(c.partition.retention_mode, RetentionMode::Drop);
+        assert!(c.multicall.enabled);
+        assert_eq!(c.multicall.address, MULTICALL3_ADDRESS);
+        assert_eq!(c.multicall.batch, 50);
+        assert_eq!(c.stats.interval, 60);
+
+        c.apply_flags(Some("http://flag:8545"), None);
+        assert_eq!(c.evm_rpc, "http://flag:8545");
+        assert_eq!(c.interval, 20);
+        c.apply_flags(None, Some(30));
+        assert_eq!(c.interval, 30);
+        c.validate().unwrap();
+    }
+
+    #[test]
+    fn invalid_env_names_the_variable() {
+        for (name, value) in [
+            ("UPDATER_INTERVAL", "soon"),
+            ("UPDATER_PARTITION_RETENTION_MODE", "archive"),
+            ("UPDATER_MULTICALL_ENABLED", "yes"),
+        ] {
+            let r = config(&[(name, value)]).map(|_| ());
+            assert_eq!(invalid_setting(r), name);
+        }
+    }
+
+    #[test]
+    fn validate_names_the_setting() {
+        for (name, value, setting) in [
+            ("UPDATER_EVM_RPC", "not a url", "evm_rpc"),
+            ("UPDATER_STAKING", "0x12", "staking"),
+            ("UPDATER_DB_URL", "mysql://localhost/db", "db_url"),
+            ("UPDATER_INTERVAL", "0", "interval"),
+            ("UPDATER_PARTITION_BLOCKS", "0", "partition.blocks"),
+            ("UPDATER_PARTITION_AHEAD", "101", "partition.ahead"),
+            (
+                "UPDATER_PARTITION_RETENTION_DAYS",
+                "0",
+                "partition.retention_days",
+            ),
+            ("UPDATER_MULTICALL_ADDRESS", "0x12", "multicall.address"),
+            ("UPDATER_MULTICALL_BATCH", "1", "multicall.batch"),
+            (
+                "UPDATER_EVENTS_POWER_THRESHOLD",
+                "-1",
+                "events.power_threshold",
+            ),
+            ("UPDATER_POSITIONS_INTERVAL", "0", "positions.interval"),
+            ("UPDATER_STATS_INTERVAL", "0", "stats.interval"),
+        ] {
+            let c = config(&[(name, value)]).unwrap();
+            assert_eq!(invalid_setting(c.validate()), setting);
+        }
+
+        // Only checked when multicall is used.
+        let c = config(&[
+            ("UPDATER_MULTICALL_ENABLED", "false"),
+            ("UPDATER_MULTICALL_ADDRESS", "0x12"),
+        ])
+        .unwrap();
+        c.validate().unwrap();
+    }
+}
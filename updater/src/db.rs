@@ -1,7 +1,62 @@
 use crate::error::Result;
+use crate::updater::ContractValidator;
+use sqlx::postgres::PgListener;
 use sqlx::types::chrono::NaiveDateTime;
 use sqlx::types::BigDecimal;
 use sqlx::{PgPool, Row};
+use std::collections::{HashMap, HashSet};
+
+/// Channel the scanner notifies with the validators touched by the staking events of a block.
+/// Must match the scanner's.
+pub const VALIDATOR_CHANNEL: &str = "evm_validator_changed";
+
+#[derive(Debug)]
+pub struct Partition {
+    pub name: String,
+    pub lower: i64,
+    pub upper: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct DelegatorPosition {
+    pub validator: String,
+    pub delegator: String,
+    pub bound: BigDecimal,
+    pub unbound: BigDecimal,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ValidatorEvent {
+    pub kind: &'static str,
+    pub old_value: String,
+    pub new_value: String,
+}
+
+#[derive(Debug)]
+pub struct RewardSnapshot {
+    pub validator: String,
+    pub staker_reward_ratio: BigDecimal,
+    pub delegator_reward_ratio: BigDecimal,
+    pub commission_unclaimed: BigDecimal,
+    pub delegators_unclaimed: BigDecimal,
+    pub delegators: i32,
+    /// Commission earned so far, claimed or not
+    pub commission_earned: BigDecimal,
+    /// Rewards the delegators earned so far, claimed or not
+    pub delegators_earned: BigDecimal,
+}
+
+#[derive(Debug)]
+pub struct NetworkStats {
+    pub block_num: i64,
+    pub tm: NaiveDateTime,
+    pub total_delegation: BigDecimal,
+    pub global_pre_issue: BigDecimal,
+    pub return_rate: BigDecimal,
+    pub active_validators: i32,
+    pub inactive_validators: i32,
+    pub total_power: BigDecimal,
+}
 
 #[derive(Debug)]
 pub struct Storage {
@@ -13,6 +68,7 @@ impl Storage {
         Self { pool }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn insert_validator(
         &self,
         block_num: i64,
@@ -58,17 +114,469 @@ impl Storage {
         Ok(())
     }
 
-    #[allow(dead_code)]
-    pub async fn get_validator_list(&self) -> Result<Vec<String>> {
-        let rows = sqlx::query("SELECT DISTINCT validator FROM evm_stakes")
+    pub async fn insert_validator_snapshot(
+        &self,
+        validator: &str,
+        v: &ContractValidator,
+    ) -> Result<()> {
+        self.insert_validator(
+            v.block_num,
+            validator,
+            &v.pubkey,
+            v.pubkey_type,
+            v.rate.clone(),
+            &v.staker,
+            v.power.clone(),
+            v.total_unbound_amount.clone(),
+            v.punish_rate.clone(),
+            v.begin_block,
+            v.active,
+            v.jailed,
+            v.unjail_datetime,
+            v.should_vote,
+            v.voted,
+        )
+        .await
+    }
+
+    pub async fn insert_validator_events(
+        &self,
+        block_num: i64,
+        tm: NaiveDateTime,
+        validator: &str,
+        events: &[ValidatorEvent],
+    ) -> Result<()> {
+        for e in events {
+            sqlx::query(
+                "INSERT INTO evm_validator_events VALUES($1,$2,$3,$4,$5,$6) \
+                    ON CONFLICT(validator,block_num,kind) DO UPDATE SET old_value=$5,new_value=$6",
+            )
+            .bind(block_num)
+            .bind(tm)
+            .bind(validator)
+            .bind(e.kind)
+            .bind(&e.old_value)
+            .bind(&e.new_value)
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_latest_validator(&self, validator: &str) -> Result<Option<ContractValidator>> {
+        self.get_validator_at(validator, i64::MAX).await
+    }
+
+    /// Block of the newest snapshot of each validator.
+    pub async fn get_latest_validator_blocks(&self) -> Result<HashMap<String, i64>> {
+        let rows = sqlx::query(
+            "SELECT validator,max(block_num) AS block_num FROM evm_validators GROUP BY validator",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut blocks = HashMap::new();
+        for r in rows {
+            blocks.insert(r.try_get("validator")?, r.try_get("block_num")?);
+        }
+        Ok(blocks)
+    }
+
+    /// Latest snapshot of `validator` taken at or before `block_num`.
+    pub async fn get_validator_at(
+        &self,
+        validator: &str,
+        block_num: i64,
+    ) -> Result<Option<ContractValidator>> {
+        let row = sqlx::query(
+            "SELECT block_num,pubkey,pubkey_type,rate,staker,power,unbound,punish_rate,begin_block,\
+                active,jailed,unjail_time,should_vote,voted FROM evm_validators WHERE validator=$1 \
+                AND block_num<=$2 ORDER BY block_num DESC LIMIT 1",
+        )
+        .bind(validator)
+        .bind(block_num)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(r) = row else {
+            return Ok(None);
+        };
+        Ok(Some(ContractValidator {
+            block_num: r.try_get("block_num")?,
+            pubkey: r.try_get("pubkey")?,
+            pubkey_type: r.try_get("pubkey_type")?,
+            rate: r.try_get("rate")?,
+            staker: r.try_get("staker")?,
+            power: r.try_get("power")?,
+            total_unbound_amount: r.try_get("unbound")?,
+            punish_rate: r.try_get("punish_rate")?,
+            begin_block: r.try_get("begin_block")?,
+            active: r.try_get("active")?,
+            jailed: r.try_get("jailed")?,
+            unjail_datetime: r.try_get("unjail_time")?,
+            should_vote: r.try_get("should_vote")?,
+            voted: r.try_get("voted")?,
+        }))
+    }
+
+    pub async fn listen(&self, channel: &str) -> Result<PgListener> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(channel).await?;
+
+        Ok(listener)
+    }
+
+    pub async fn upsert_heartbeat(
+        &self,
+        validators: &[String],
+        block_num: i64,
+        checked_at: NaiveDateTime,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO evm_validator_heartbeat SELECT v,$2,$3 FROM unnest($1::varchar[]) v \
+                ON CONFLICT(validator) DO UPDATE SET block_num=$2,checked_at=$3",
+        )
+        .bind(validators)
+        .bind(block_num)
+        .bind(checked_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn is_backfilled(&self, block_num: i64) -> Result<bool> {
+        let row = sqlx::query("SELECT 1 FROM evm_validator_backfill WHERE block_num=$1")
+            .bind(block_num)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    pub async fn mark_backfilled(
+        &self,
+        block_num: i64,
+        validators: i32,
+        tm: NaiveDateTime,
+    ) -> Result<()> {
+        sqlx::query("INSERT INTO evm_validator_backfill VALUES($1,$2,$3) ON CONFLICT DO NOTHING")
+            .bind(block_num)
+            .bind(validators)
+            .bind(tm)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_delegation_pairs(&self) -> Result<Vec<(String, String)>> {
+        let rows = sqlx::query("SELECT DISTINCT validator,delegator FROM evm_audit")
             .fetch_all(&self.pool)
             .await?;
 
-        let validators = rows
+        let mut pairs = vec![];
+        for r in rows {
+            pairs.push((r.try_get("validator")?, r.try_get("delegator")?));
+        }
+
+        Ok(pairs)
+    }
+
+    /// Latest stored position of every pair, keyed by (validator, delegator).
+    pub async fn get_latest_positions(
+        &self,
+    ) -> Result<HashMap<(String, String), DelegatorPosition>> {
+        let rows = sqlx::query(
+            "SELECT DISTINCT ON (validator,delegator) validator,delegator,bound,unbound \
+                FROM evm_delegator_positions ORDER BY validator,delegator,block_num DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut positions = HashMap::new();
+        for r in rows {
+            let p = DelegatorPosition {
+                validator: r.try_get("validator")?,
+                delegator: r.try_get("delegator")?,
+                bound: r.try_get("bound")?,
+                unbound: r.try_get("unbound")?,
+            };
+            positions.insert((p.validator.clone(), p.delegator.clone()), p);
+        }
+
+        Ok(positions)
+    }
+
+    /// Latest stored commission of every validator as (unclaimed, earned).
+    pub async fn get_latest_commissions(
+        &self,
+    ) -> Result<HashMap<String, (BigDecimal, BigDecimal)>> {
+        let rows = sqlx::query(
+            "SELECT DISTINCT ON (validator) validator,commission_unclaimed,commission_earned \
+                FROM evm_reward_snapshots ORDER BY validator,block_num DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut commissions = HashMap::new();
+        for r in rows {
+            commissions.insert(
+                r.try_get("validator")?,
+                (
+                    r.try_get("commission_unclaimed")?,
+                    r.try_get("commission_earned")?,
+                ),
+            );
+        }
+
+        Ok(commissions)
+    }
+
+    /// Rewards claimed from each validator up to `block_num`, from the indexed CoinbaseMint events.
+    pub async fn get_claimed_rewards(&self, block_num: i64) -> Result<HashMap<String, BigDecimal>> {
+        let rows = sqlx::query(
+            "SELECT validator,sum(amount) AS claimed FROM evm_coinbase_mint \
+                WHERE block_num<=$1 GROUP BY validator",
+        )
+        .bind(block_num)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut claimed = HashMap::new();
+        for r in rows {
+            claimed.insert(r.try_get("validator")?, r.try_get("claimed")?);
+        }
+
+        Ok(claimed)
+    }
+
+    /// Writes `positions` and, if `complete`, records the run at `block_num` in one transaction.
+    pub async fn insert_positions(
+        &self,
+        block_num: i64,
+        tm: NaiveDateTime,
+        positions: &[DelegatorPosition],
+        complete: bool,
+    ) -> Result<()> {
+        let validators = positions
+            .iter()
+            .map(|p| p.validator.clone())
+            .collect::<Vec<_>>();
+        let delegators = positions
+            .iter()
+            .map(|p| p.delegator.clone())
+            .collect::<Vec<_>>();
+        let bounds = positions
             .iter()
-            .map(|r| r.get("validator"))
-            .collect::<Vec<String>>();
+            .map(|p| p.bound.clone())
+            .collect::<Vec<_>>();
+        let unbounds = positions
+            .iter()
+            .map(|p| p.unbound.clone())
+            .collect::<Vec<_>>();
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO evm_delegator_positions SELECT $1,$2,v,d,b,u \
+                FROM unnest($3::varchar[],$4::varchar[],$5::numeric[],$6::numeric[]) AS t(v,d,b,u) \
+                ON CONFLICT(validator,delegator,block_num) DO UPDATE SET bound=excluded.bound,unbound=excluded.unbound",
+        )
+        .bind(block_num)
+        .bind(tm)
+        .bind(validators)
+        .bind(delegators)
+        .bind(bounds)
+        .bind(unbounds)
+        .execute(&mut *tx)
+        .await?;
+        // The indexer trusts positions as of the latest run, which must cover every pair.
+        if complete {
+            sqlx::query(
+                "INSERT INTO evm_delegator_position_runs VALUES($1,$2) ON CONFLICT DO NOTHING",
+            )
+            .bind(block_num)
+            .bind(tm)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Indices of queue entries already stored in `[start,end)`.
+    pub async fn get_queued_undelegations(&self, start: i64, end: i64) -> Result<HashSet<i64>> {
+        let rows = sqlx::query("SELECT idx FROM evm_undelegation_queue WHERE idx>=$1 AND idx<$2")
+            .bind(start)
+            .bind(end)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut indices = HashSet::new();
+        for r in rows {
+            indices.insert(r.try_get("idx")?);
+        }
+
+        Ok(indices)
+    }
+
+    pub async fn insert_queued_undelegation(
+        &self,
+        idx: i64,
+        validator: &str,
+        delegator: &str,
+        amount: BigDecimal,
+        unlock_time: i64,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO evm_undelegation_queue(idx,validator,delegator,amount,unlock_time) \
+                VALUES($1,$2,$3,$4,$5) ON CONFLICT(idx) DO NOTHING",
+        )
+        .bind(idx)
+        .bind(validator)
+        .bind(delegator)
+        .bind(amount)
+        .bind(unlock_time)
+        .execute(&self.pool)
+        .await?;
 
-        Ok(validators)
+        Ok(())
     }
+
+    /// Marks entries below the queue start as released, returns how many were marked.
+    pub async fn release_undelegations(&self, start: i64, block_num: i64) -> Result<u64> {
+        let r = sqlx::query(
+            "UPDATE evm_undelegation_queue SET released=true,released_block=$2 \
+                WHERE idx<$1 AND NOT released",
+        )
+        .bind(start)
+        .bind(block_num)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(r.rows_affected())
+    }
+
+    pub async fn insert_reward_snapshots(
+        &self,
+        block_num: i64,
+        tm: NaiveDateTime,
+        snapshots: &[RewardSnapshot],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for s in snapshots {
+            sqlx::query(
+                "INSERT INTO evm_reward_snapshots VALUES($1,$2,$3,$4,$5,$6,$7,$8,$9,$10) \
+                    ON CONFLICT(validator,block_num) DO NOTHING",
+            )
+            .bind(block_num)
+            .bind(tm)
+            .bind(&s.validator)
+            .bind(&s.staker_reward_ratio)
+            .bind(&s.delegator_reward_ratio)
+            .bind(&s.commission_unclaimed)
+            .bind(&s.delegators_unclaimed)
+            .bind(s.delegators)
+            .bind(&s.commission_earned)
+            .bind(&s.delegators_earned)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn insert_network_stats(&self, stats: NetworkStats) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO evm_network_stats VALUES($1,$2,$3,$4,$5,$6,$7,$8) ON CONFLICT(block_num) DO NOTHING",
+        )
+        .bind(stats.block_num)
+        .bind(stats.tm)
+        .bind(stats.total_delegation)
+        .bind(stats.global_pre_issue)
+        .bind(stats.return_rate)
+        .bind(stats.active_validators)
+        .bind(stats.inactive_validators)
+        .bind(stats.total_power)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_validator_partitions(&self) -> Result<Vec<Partition>> {
+        let rows = sqlx::query(
+            "SELECT c.relname,pg_get_expr(c.relpartbound,c.oid) bound FROM pg_inherits i \
+                JOIN pg_class c ON c.oid=i.inhrelid WHERE i.inhparent='evm_validators'::regclass",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut partitions = vec![];
+        for r in rows {
+            let name: String = r.try_get("relname")?;
+            let bound: String = r.try_get("bound")?;
+            match parse_range_bound(&bound) {
+                Some((lower, upper)) => partitions.push(Partition { name, lower, upper }),
+                None => return Err(format!("unexpected bound of {}: {}", name, bound).into()),
+            }
+        }
+        partitions.sort_by_key(|p| p.lower);
+
+        Ok(partitions)
+    }
+
+    pub async fn create_validator_partition(&self, lower: i64, upper: i64) -> Result<String> {
+        let name = format!("evm_validators_p{}", lower);
+        sqlx::query(&format!(
+            "CREATE TABLE {} PARTITION OF evm_validators FOR VALUES FROM ({}) TO ({})",
+            name, lower, upper
+        ))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(name)
+    }
+
+    /// Detaches partition `name` and renames it to `detached`, freeing its name for a new
+    /// partition of the same range.
+    pub async fn detach_validator_partition(&self, name: &str, detached: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(&format!(
+            "ALTER TABLE evm_validators DETACH PARTITION {}",
+            name
+        ))
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(&format!("ALTER TABLE {} RENAME TO {}", name, detached))
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn drop_validator_partition(&self, name: &str) -> Result<()> {
+        sqlx::query(&format!("DROP TABLE {}", name))
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+// FOR VALUES FROM (MINVALUE) TO ('100')
+fn parse_range_bound(bound: &str) -> Option<(i64, i64)> {
+    let parse = |v: &str| match v.trim().trim_matches('\'') {
+        "MINVALUE" => Some(i64::MIN),
+        "MAXVALUE" => Some(i64::MAX),
+        n => n.parse().ok(),
+    };
+    let (from, to) = bound
+        .strip_prefix("FOR VALUES FROM (")?
+        .split_once(") TO (")?;
+    Some((parse(from)?, parse(to.strip_suffix(')')?)?))
 }
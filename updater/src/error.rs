@@ -1,4 +1,4 @@
-use ethers::contract::ContractError;
+use ethers::contract::{ContractError, MulticallError};
 
 use ethers::providers::ProviderError;
 use ethers::providers::{Http, Provider};
@@ -6,16 +6,41 @@ use ethers::providers::{Http, Provider};
 #[derive(Debug)]
 pub enum UpdaterError {
     Custom(String),
+    /// Setting name and why its value is invalid
+    InvalidSetting(String, String),
     DBError(sqlx::Error),
+    MigrateError(sqlx::migrate::MigrateError),
     JoinError(tokio::task::JoinError),
     ParseUrlError(url::ParseError),
     HexError(rustc_hex::FromHexError),
     TomlDeError(toml::de::Error),
     IOError(std::io::Error),
     EthersContractError(ContractError<Provider<Http>>),
+    EthersMulticallError(MulticallError<Provider<Http>>),
     EthersProviderError(ProviderError),
 }
 
+impl std::fmt::Display for UpdaterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdaterError::Custom(e) => write!(f, "{}", e),
+            UpdaterError::InvalidSetting(setting, reason) => {
+                write!(f, "invalid setting {}: {}", setting, reason)
+            }
+            UpdaterError::DBError(e) => write!(f, "db: {}", e),
+            UpdaterError::MigrateError(e) => write!(f, "migrate: {}", e),
+            UpdaterError::JoinError(e) => write!(f, "join: {}", e),
+            UpdaterError::ParseUrlError(e) => write!(f, "url: {}", e),
+            UpdaterError::HexError(e) => write!(f, "hex: {}", e),
+            UpdaterError::TomlDeError(e) => write!(f, "toml: {}", e),
+            UpdaterError::IOError(e) => write!(f, "io: {}", e),
+            UpdaterError::EthersContractError(e) => write!(f, "contract: {}", e),
+            UpdaterError::EthersMulticallError(e) => write!(f, "multicall: {}", e),
+            UpdaterError::EthersProviderError(e) => write!(f, "provider: {}", e),
+        }
+    }
+}
+
 impl From<ProviderError> for UpdaterError {
     fn from(e: ProviderError) -> Self {
         UpdaterError::EthersProviderError(e)
@@ -28,6 +53,12 @@ impl From<ContractError<Provider<Http>>> for UpdaterError {
     }
 }
 
+impl From<MulticallError<Provider<Http>>> for UpdaterError {
+    fn from(e: MulticallError<Provider<Http>>) -> Self {
+        UpdaterError::EthersMulticallError(e)
+    }
+}
+
 impl From<String> for UpdaterError {
     fn from(e: String) -> Self {
         UpdaterError::Custom(e)
@@ -46,6 +77,12 @@ impl From<sqlx::Error> for UpdaterError {
     }
 }
 
+impl From<sqlx::migrate::MigrateError> for UpdaterError {
+    fn from(e: sqlx::migrate::MigrateError) -> Self {
+        UpdaterError::MigrateError(e)
+    }
+}
+
 impl From<tokio::task::JoinError> for UpdaterError {
     fn from(e: tokio::task::JoinError) -> Self {
         UpdaterError::JoinError(e)
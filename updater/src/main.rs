@@ -1,65 +1,87 @@
+mod config;
 mod db;
 mod error;
 mod updater;
 
+use crate::config::{UpdaterConfig, DEFAULT_CONFIG_PATH};
 use crate::db::Storage;
-use crate::error::Result;
-use crate::updater::Updater;
-use clap::Parser;
-use ethers::contract::abigen;
-use ethers::prelude::{Http, Provider};
+use crate::error::{Result, UpdaterError};
+use crate::updater::{MulticallBatch, Updater};
+use clap::{Parser, Subcommand};
+use ethers::contract::{abigen, Multicall};
+use ethers::prelude::{Http, Middleware, Provider};
 use ethers::types::Address;
-use log::info;
-use serde::{Deserialize, Serialize};
+use log::{info, warn};
 use sqlx::pool::PoolOptions;
 use sqlx::{Pool, Postgres};
-use std::fs::File;
-use std::io::Read;
 use std::sync::Arc;
 use std::time::Duration;
 
-const DEFAULT_RPC_RETRIES: usize = 3;
-const DEFAULT_INTERVAL: u64 = 14; // 14s
+const DEFAULT_BACKFILL_STEP: u64 = 1000;
 
 abigen!(RewardContract, "../abi/Reward.json");
 abigen!(StakingContract, "../abi/Staking.json");
 
-#[derive(Serialize, Deserialize)]
-struct UpdaterConfig {
-    pub evm_rpc: String,
-    pub staking: String,
-    pub reward: String,
-    pub db_url: String,
-}
-
-impl UpdaterConfig {
-    pub fn new(file_path: &str) -> Result<Self> {
-        let mut f = File::open(file_path)?;
-        let mut s = String::new();
-        f.read_to_string(&mut s)?;
-        let c: UpdaterConfig = toml::from_str(&s)?;
-        Ok(c)
-    }
-}
-
+/// Settings are read from the config file, then `UPDATER_*` environment variables,
+/// then these flags, each overriding the previous one.
 #[derive(Parser, Debug)]
 struct Args {
-    /// Node RPC
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Path of the config file
+    #[arg(long, global = true, default_value = DEFAULT_CONFIG_PATH)]
+    pub config: String,
+    /// Node RPC, overrides `evm_rpc`
     #[arg(long)]
     pub node: Option<String>,
-    /// Block height to start scanning
-    #[arg(long)]
-    pub start: Option<u64>,
-    /// Interval of scanning in seconds
+    /// Interval of updating in seconds, overrides `interval`
     #[arg(long)]
     pub interval: Option<u64>,
+    /// Ignored, the updater always reads the latest block. Use `backfill --start` for past blocks.
+    #[arg(long, hide = true)]
+    pub start: Option<u64>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Apply pending schema migrations and exit
+    Migrate,
+    /// Replay validator snapshots at past blocks and exit
+    Backfill {
+        /// First block to replay
+        #[arg(long)]
+        start: u64,
+        /// Last block to replay
+        #[arg(long)]
+        end: u64,
+        /// Blocks between two replayed blocks
+        #[arg(long, default_value_t = DEFAULT_BACKFILL_STEP)]
+        step: u64,
+        /// Archive node RPC, `evm_rpc` of the config by default
+        #[arg(long)]
+        node: Option<String>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
+    let args = Args::parse();
 
-    let config = UpdaterConfig::new("./config.toml")?;
+    if args.start.is_some() {
+        warn!("--start is ignored, use `backfill --start` to replay past blocks");
+    }
+    let mut config = UpdaterConfig::load(&args.config)?;
+    config.apply_flags(args.node.as_deref(), args.interval);
+    if let Some(Command::Backfill {
+        node: Some(ref node),
+        ..
+    }) = args.command
+    {
+        config.evm_rpc = node.clone();
+    }
+    config.validate()?;
+    info!("Config: {}", args.config);
     info!("EVM RPC: {}", config.evm_rpc);
     info!("Staking contract: {}", config.staking);
     info!("Reward contract: {}", config.reward);
@@ -70,13 +92,14 @@ async fn main() -> Result<()> {
         .expect("can't connect to database");
     info!("Connecting db...ok");
 
+    if let Some(Command::Migrate) = args.command {
+        schema::run_migrations(&pool).await?;
+        return Ok(());
+    }
+    schema::check_schema_version(&pool, &["updater"]).await?;
+
     let storage = Storage::new(pool);
-    let args = Args::parse();
-    let interval = if let Some(interval) = args.interval {
-        Duration::from_secs(interval)
-    } else {
-        Duration::from_secs(DEFAULT_INTERVAL)
-    };
+    let interval = Duration::from_secs(config.interval);
 
     let provider = Provider::<Http>::try_from(config.evm_rpc)?;
     let staking_addr: Address = config.staking.parse()?;
@@ -84,8 +107,69 @@ async fn main() -> Result<()> {
     let reward_addr: Address = config.reward.parse()?;
     let reward = RewardContract::new(reward_addr, Arc::new(provider.clone()));
     info!("Updating interval: {}s", interval.as_secs());
-    let updater = Updater::new(DEFAULT_RPC_RETRIES, provider, staking, reward, storage);
-    let _ = updater.run().await?;
+    info!("Partition: {:?}", config.partition);
+    info!("Positions: {:?}", config.positions);
+    info!("Stats: {:?}", config.stats);
+    info!("Events: {:?}", config.events);
+    // Multicall3 may not be deployed yet at the blocks being backfilled.
+    let backfill = matches!(args.command, Some(Command::Backfill { .. }));
+    let multicall = if config.multicall.enabled && !backfill {
+        let c = config.multicall;
+        let addr: Address = c.address.parse()?;
+        // A failed check keeps Multicall, failed batches are read directly anyway.
+        let deployed = match provider.get_code(addr, None).await {
+            Ok(code) => !code.is_empty(),
+            Err(e) => {
+                warn!("Check Multicall3 contract at {} error: {:?}", c.address, e);
+                true
+            }
+        };
+        if !deployed {
+            warn!(
+                "No Multicall3 contract at {}, reading with one call each",
+                c.address
+            );
+            None
+        } else {
+            info!("Multicall: {}, batch: {}", c.address, c.batch);
+            let multicall = Multicall::new(Arc::new(provider.clone()), Some(addr)).await?;
+            Some(MulticallBatch {
+                multicall,
+                batch: c.batch,
+            })
+        }
+    } else {
+        None
+    };
+    let updater = Updater::new(
+        config.retries,
+        provider,
+        staking,
+        reward,
+        storage,
+        config.partition,
+        config.positions,
+        config.stats,
+        config.events,
+        multicall,
+    );
+    if let Some(Command::Backfill {
+        start, end, step, ..
+    }) = args.command
+    {
+        if step == 0 {
+            return Err(UpdaterError::InvalidSetting(
+                "backfill --step".to_string(),
+                "must be greater than 0".to_string(),
+            ));
+        }
+        return updater.backfill(start, end, step).await;
+    }
+    tokio::try_join!(
+        updater.run(interval),
+        updater.run_positions(),
+        updater.run_listener()
+    )?;
 
     Ok(())
 }
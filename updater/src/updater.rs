@@ -1,19 +1,34 @@
-use crate::db::Storage;
+use crate::config::{EventConfig, PartitionConfig, PositionConfig, RetentionMode, StatsConfig};
+use crate::db::{
+    DelegatorPosition, NetworkStats, Partition, RewardSnapshot, Storage, ValidatorEvent,
+    VALIDATOR_CHANNEL,
+};
 use crate::error::Result;
-use crate::{RewardContract, StakingContract, DEFAULT_INTERVAL};
+use crate::{RewardContract, StakingContract};
 use crossbeam::channel::bounded;
+use ethers::abi::{Detokenize, Tokenizable};
+use ethers::contract::{ContractCall, Multicall};
 use ethers::prelude::{Http, Provider, H160};
 use ethers::providers::Middleware;
-use ethers::types::Address;
+use ethers::types::{Address, Bytes, U256};
 use ethers::utils::hex::encode_prefixed;
-use log::{error, info};
+use log::{error, info, warn};
 
 use sqlx::types::BigDecimal;
 use std::str::FromStr;
 
-use sqlx::types::chrono::{DateTime, NaiveDateTime};
-use std::sync::Arc;
-use std::time::Duration;
+use serde::Deserialize;
+use sqlx::types::chrono::{DateTime, NaiveDateTime, Utc};
+use std::collections::{BTreeSet, HashMap};
+use std::future::Future;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const LISTEN_RETRY_DELAY: u64 = 5; // 5s
+const NOTIFY_COALESCE_MS: u64 = 200;
+/// Delegators passed to one `commissionUnclaimed` call
+const COMMISSION_DELEGATORS_PER_CALL: usize = 100;
 
 #[derive(Debug)]
 pub struct RpcCaller {
@@ -22,38 +37,205 @@ pub struct RpcCaller {
     pub staking: StakingContract<Provider<Http>>,
     pub reward: RewardContract<Provider<Http>>,
     pub storage: Storage,
+    /// Latest persisted snapshot of each validator
+    pub snapshots: Mutex<HashMap<Address, ContractValidator>>,
+    /// Serializes the writes of each validator between the periodic cycle and the listener
+    pub persist_locks: Mutex<HashMap<Address, Arc<tokio::sync::Mutex<()>>>>,
+    pub multicall: Option<MulticallBatch>,
+    /// Minimum power change in percent recorded as a transition
+    pub power_threshold: BigDecimal,
+}
+
+/// Aggregates contract reads into Multicall3 calls of at most `batch` reads each.
+#[derive(Debug, Clone)]
+pub struct MulticallBatch {
+    pub multicall: Multicall<Provider<Http>>,
+    pub batch: usize,
 }
 
-#[derive(Default)]
+impl RpcCaller {
+    /// Lock held while a snapshot of `vaddr` is compared with the latest one and written.
+    fn persist_lock(&self, vaddr: Address) -> Arc<tokio::sync::Mutex<()>> {
+        self.persist_locks
+            .lock()
+            .unwrap()
+            .entry(vaddr)
+            .or_default()
+            .clone()
+    }
+
+    async fn last_snapshot(&self, vaddr: Address) -> Result<Option<ContractValidator>> {
+        if let Some(v) = self.snapshots.lock().unwrap().get(&vaddr) {
+            return Ok(Some(v.clone()));
+        }
+        self.storage
+            .get_latest_validator(&encode_prefixed(vaddr.as_bytes()))
+            .await
+    }
+
+    /// Timestamp of block `block_num`, records taken at a block are stamped with it.
+    async fn block_time(&self, block_num: u64) -> Result<NaiveDateTime> {
+        let block = with_retries(self.retries, || self.provider.get_block(block_num)).await?;
+        Ok(match block {
+            Some(b) => DateTime::from_timestamp(b.timestamp.as_u64() as i64, 0)
+                .unwrap_or_default()
+                .naive_utc(),
+            None => Utc::now().naive_utc(),
+        })
+    }
+}
+
+#[derive(Default, Clone, Debug)]
 pub struct ContractValidator {
-    block_num: i64,
-    pubkey: String,
-    pubkey_type: i32,
-    rate: BigDecimal,
-    staker: String,
-    power: BigDecimal,
-    total_unbound_amount: BigDecimal,
-    punish_rate: BigDecimal,
-    begin_block: i64,
-    active: bool,
-    jailed: bool,
-    unjail_datetime: NaiveDateTime,
-    should_vote: i32,
-    voted: i32,
+    pub block_num: i64,
+    pub pubkey: String,
+    pub pubkey_type: i32,
+    pub rate: BigDecimal,
+    pub staker: String,
+    pub power: BigDecimal,
+    pub total_unbound_amount: BigDecimal,
+    pub punish_rate: BigDecimal,
+    pub begin_block: i64,
+    pub active: bool,
+    pub jailed: bool,
+    pub unjail_datetime: NaiveDateTime,
+    pub should_vote: i32,
+    pub voted: i32,
+}
+
+//    struct ValidatorData {
+//         bytes publicKey;
+//         PublicKeyType ty;
+//         uint256 rate;
+//         address staker;
+//         uint256 power;
+//         uint256 totalUnboundAmount;
+//         uint256 punishRate;
+//         uint256 beginBlock;
+//     }
+type ValidatorData = (Bytes, u8, U256, Address, U256, U256, U256, U256);
+
+//  struct ValidatorStatus {
+//         uint256 heapIndexOff1;
+//         bool isActive;
+//         bool jailed;
+//         uint64 unjailDatetime;
+//         uint16 shouldVote;
+//         uint16 voted;
+//  }
+type ValidatorStatus = (U256, bool, bool, u64, u16, u16);
+
+impl ContractValidator {
+    fn set_data(&mut self, vdata: ValidatorData) {
+        self.pubkey = vdata.0.to_string();
+        self.pubkey_type = vdata.1 as i32;
+        self.rate = BigDecimal::from_str(&vdata.2.to_string()).unwrap_or_default();
+        self.staker = encode_prefixed(vdata.3.as_bytes());
+        self.power = BigDecimal::from_str(&vdata.4.to_string()).unwrap_or_default();
+        self.total_unbound_amount = BigDecimal::from_str(&vdata.5.to_string()).unwrap_or_default();
+        self.punish_rate = BigDecimal::from_str(&vdata.6.to_string()).unwrap_or_default();
+        self.begin_block = vdata.7.as_u64() as i64;
+    }
+
+    fn set_status(&mut self, vstatus: ValidatorStatus) {
+        self.active = vstatus.1;
+        self.jailed = vstatus.2;
+        self.unjail_datetime = DateTime::from_timestamp(vstatus.3 as i64, 0)
+            .unwrap()
+            .naive_utc();
+        self.should_vote = vstatus.4 as i32;
+        self.voted = vstatus.5 as i32;
+    }
+
+    /// Whether two snapshots differ in anything but the block they were taken at.
+    pub fn same_state(&self, other: &ContractValidator) -> bool {
+        self.pubkey == other.pubkey
+            && self.pubkey_type == other.pubkey_type
+            && self.rate == other.rate
+            && self.staker == other.staker
+            && self.power == other.power
+            && self.total_unbound_amount == other.total_unbound_amount
+            && self.punish_rate == other.punish_rate
+            && self.begin_block == other.begin_block
+            && self.active == other.active
+            && self.jailed == other.jailed
+            && self.unjail_datetime == other.unjail_datetime
+            && self.should_vote == other.should_vote
+            && self.voted == other.voted
+    }
+
+    /// Transitions from this snapshot to the next one of the same validator.
+    /// A power change is only reported if it is at least `power_threshold` percent.
+    pub fn transitions(
+        &self,
+        next: &ContractValidator,
+        power_threshold: &BigDecimal,
+    ) -> Vec<ValidatorEvent> {
+        let event = |kind, old_value: String, new_value: String| ValidatorEvent {
+            kind,
+            old_value,
+            new_value,
+        };
+        let mut events = vec![];
+        if self.active != next.active {
+            let kind = if next.active { "active" } else { "inactive" };
+            events.push(event(
+                kind,
+                self.active.to_string(),
+                next.active.to_string(),
+            ));
+        }
+        if self.jailed != next.jailed {
+            let kind = if next.jailed { "jailed" } else { "unjailed" };
+            events.push(event(
+                kind,
+                self.jailed.to_string(),
+                next.jailed.to_string(),
+            ));
+        }
+        let change = (&next.power - &self.power).abs() * BigDecimal::from(100);
+        if self.power != next.power && change >= &self.power * power_threshold {
+            events.push(event(
+                "power",
+                self.power.to_string(),
+                next.power.to_string(),
+            ));
+        }
+        if self.rate != next.rate {
+            events.push(event("rate", self.rate.to_string(), next.rate.to_string()));
+        }
+        if self.punish_rate != next.punish_rate {
+            events.push(event(
+                "punish_rate",
+                self.punish_rate.to_string(),
+                next.punish_rate.to_string(),
+            ));
+        }
+        events
+    }
 }
 
 #[derive(Debug)]
 pub struct Updater {
     caller: Arc<RpcCaller>,
+    partition: PartitionConfig,
+    positions: PositionConfig,
+    stats: StatsConfig,
 }
 
 impl Updater {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         retries: usize,
         provider: Provider<Http>,
         staking: StakingContract<Provider<Http>>,
         reward: RewardContract<Provider<Http>>,
         storage: Storage,
+        partition: PartitionConfig,
+        positions: PositionConfig,
+        stats: StatsConfig,
+        events: EventConfig,
+        multicall: Option<MulticallBatch>,
     ) -> Self {
         let caller = RpcCaller {
             retries,
@@ -61,16 +243,189 @@ impl Updater {
             staking,
             reward,
             storage,
+            snapshots: Mutex::new(HashMap::new()),
+            persist_locks: Mutex::new(HashMap::new()),
+            multicall,
+            power_threshold: BigDecimal::try_from(events.power_threshold).unwrap_or_default(),
         };
 
         Self {
             caller: Arc::new(caller),
+            partition,
+            positions,
+            stats,
         }
     }
 
-    pub async fn update_validators(&self, validators: Vec<H160>) -> Result<u64> {
-        let block_num = self.caller.provider.get_block_number().await?.as_u64();
+    /// Creates the partition holding `block_num` and `ahead` partitions after it.
+    pub async fn ensure_partitions(&self, block_num: u64, ahead: u64) -> Result<()> {
+        let size = self.partition.blocks as i64;
+        let mut partitions = self.caller.storage.get_validator_partitions().await?;
+        for i in 0..=ahead as i64 {
+            let block = block_num as i64 + i * size;
+            if let Some((lower, upper)) = partition_range(&partitions, block, size) {
+                let name = self
+                    .caller
+                    .storage
+                    .create_validator_partition(lower, upper)
+                    .await?;
+                info!(
+                    "Created partition {} for blocks [{},{})",
+                    name, lower, upper
+                );
+                partitions.push(Partition { name, lower, upper });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Detaches or drops the oldest partitions whose last block is older than `retention_days`.
+    /// Snapshots are only written on change, so the newest snapshot of a validator that hasn't
+    /// changed since is copied to `block_num` first.
+    pub async fn apply_retention(&self, block_num: u64) -> Result<()> {
+        let Some(days) = self.partition.retention_days else {
+            return Ok(());
+        };
+        let cutoff = Utc::now().timestamp() - days as i64 * 24 * 60 * 60;
+
+        for p in self.caller.storage.get_validator_partitions().await? {
+            if p.upper > block_num as i64 {
+                break;
+            }
+            let Some(last) = self.caller.provider.get_block((p.upper - 1) as u64).await? else {
+                break;
+            };
+            if last.timestamp.as_u64() as i64 >= cutoff {
+                break;
+            }
+            let latest = self.caller.storage.get_latest_validator_blocks().await?;
+            for addr in newest_in_partition(&latest, &p) {
+                if let Some(mut v) = self.caller.storage.get_latest_validator(&addr).await? {
+                    v.block_num = block_num as i64;
+                    self.caller
+                        .storage
+                        .insert_validator_snapshot(&addr, &v)
+                        .await?;
+                }
+            }
+            match self.partition.retention_mode {
+                RetentionMode::Detach => {
+                    let detached = format!("{}_detached_{}", p.name, Utc::now().timestamp());
+                    self.caller
+                        .storage
+                        .detach_validator_partition(&p.name, &detached)
+                        .await?
+                }
+                RetentionMode::Drop => {
+                    self.caller
+                        .storage
+                        .drop_validator_partition(&p.name)
+                        .await?
+                }
+            }
+            info!(
+                "Retention: {:?} partition {} for blocks [{},{})",
+                self.partition.retention_mode, p.name, p.lower, p.upper
+            );
+        }
 
+        Ok(())
+    }
+
+    /// Snapshots `validators` with every contract read pinned to `block_num`.
+    pub async fn update_validators(&self, block_num: u64, validators: Vec<H160>) -> Result<u64> {
+        // Validators whose reads failed keep their previous heartbeat.
+        let addrs = self
+            .refresh_validators(block_num, validators)
+            .await?
+            .iter()
+            .map(|v| encode_prefixed(v.as_bytes()))
+            .collect::<Vec<String>>();
+
+        self.caller
+            .storage
+            .upsert_heartbeat(&addrs, block_num as i64, Utc::now().naive_utc())
+            .await?;
+
+        Ok(block_num)
+    }
+
+    /// Snapshots `validators` at `block_num` without marking them checked in the heartbeat,
+    /// which only `update_validators` does for the full list. Returns the validators read.
+    async fn refresh_validators(
+        &self,
+        block_num: u64,
+        validators: Vec<H160>,
+    ) -> Result<Vec<Address>> {
+        self.ensure_partitions(block_num, self.partition.ahead)
+            .await?;
+        if let Some(ref mc) = self.caller.multicall {
+            match self
+                .update_validators_batched(mc, block_num, &validators)
+                .await
+            {
+                Ok(read) => return Ok(read),
+                Err(e) => warn!(
+                    "Multicall reads at block {} failed, reading validators directly: {:?}",
+                    block_num, e
+                ),
+            }
+        }
+        self.update_validators_pooled(block_num, validators).await
+    }
+
+    /// Refreshes the validators the scanner notifies on `VALIDATOR_CHANNEL` right after it indexed
+    /// their staking events. The periodic full refresh of `run` stays the safety net.
+    pub async fn run_listener(&self) -> Result<()> {
+        loop {
+            if let Err(e) = self.listen().await {
+                error!("Listen on {} error: {:?}", VALIDATOR_CHANNEL, e);
+            }
+
+            tokio::time::sleep(Duration::from_secs(LISTEN_RETRY_DELAY)).await
+        }
+    }
+
+    async fn listen(&self) -> Result<()> {
+        let mut listener = self.caller.storage.listen(VALIDATOR_CHANNEL).await?;
+        info!("Listening on {}", VALIDATOR_CHANNEL);
+        loop {
+            let mut validators = BTreeSet::new();
+            let notification = listener.recv().await?;
+            collect_notified(&mut validators, notification.payload());
+            // Coalesce a burst of notifications, e.g. while the scanner catches up, into one refresh.
+            while let Ok(notification) =
+                tokio::time::timeout(Duration::from_millis(NOTIFY_COALESCE_MS), listener.recv())
+                    .await
+            {
+                collect_notified(&mut validators, notification?.payload());
+            }
+            if validators.is_empty() {
+                continue;
+            }
+
+            let block_num = self.caller.provider.get_block_number().await?.as_u64();
+            let count = validators.len();
+            match self
+                .refresh_validators(block_num, validators.into_iter().collect())
+                .await
+            {
+                Ok(_) => info!(
+                    "Refresh {} notified validators at block {}",
+                    count, block_num
+                ),
+                Err(e) => error!("Refresh notified validators error: {:?}", e),
+            }
+        }
+    }
+
+    /// Reads each validator with its own calls, concurrently.
+    async fn update_validators_pooled(
+        &self,
+        block_num: u64,
+        validators: Vec<H160>,
+    ) -> Result<Vec<Address>> {
         let count = validators.len();
         let (sender, receiver) = bounded(count);
         let caller_cloned = self.caller.clone();
@@ -90,119 +445,786 @@ impl Updater {
             .map(move |_| {
                 let r = receiver.clone();
                 tokio::spawn(async move {
+                    let mut read = vec![];
                     while let Ok(Some(fut)) = r.recv() {
-                        let _ = fut.await;
+                        if let Ok(Some(vaddr)) = fut.await {
+                            read.push(vaddr);
+                        }
                     }
+                    read
                 })
             })
             .collect();
 
+        let mut read = vec![];
         for h in consumer_handles {
-            h.await?;
+            read.extend(h.await?);
         }
         producer_handle.await?;
 
+        Ok(read)
+    }
+
+    /// Reads validators through Multicall, two reads per validator.
+    async fn update_validators_batched(
+        &self,
+        mc: &MulticallBatch,
+        block_num: u64,
+        validators: &[H160],
+    ) -> Result<Vec<Address>> {
+        let staking = &self.caller.staking;
+        let mut read = vec![];
+        for chunk in validators.chunks((mc.batch / 2).max(1)) {
+            let mut multicall = mc.multicall.clone().block(block_num);
+            multicall.clear_calls();
+            for vaddr in chunk {
+                multicall
+                    .add_call(staking.validators(*vaddr), true)
+                    .add_call(staking.validator_status(*vaddr), true);
+            }
+            let results = with_retries(self.caller.retries, || multicall.call_raw()).await?;
+
+            for (vaddr, r) in chunk.iter().zip(results.chunks(2)) {
+                let vdata = r[0].clone().map(ValidatorData::from_token);
+                let vstatus = r[1].clone().map(ValidatorStatus::from_token);
+                let validator = match (vdata, vstatus) {
+                    (Ok(Ok(vdata)), Ok(Ok(vstatus))) => {
+                        let mut validator = ContractValidator {
+                            block_num: block_num as i64,
+                            ..Default::default()
+                        };
+                        validator.set_data(vdata);
+                        validator.set_status(vstatus);
+                        Some(validator)
+                    }
+                    _ => {
+                        warn!(
+                            "Multicall read of validator {:?} failed, reading it directly",
+                            vaddr
+                        );
+                        read_validator(&self.caller, block_num, *vaddr).await
+                    }
+                };
+                match validator {
+                    Some(validator) => {
+                        persist_validator(&self.caller, *vaddr, validator).await?;
+                        read.push(*vaddr);
+                    }
+                    None => error!("Skip validator {:?} at block {}", vaddr, block_num),
+                }
+            }
+        }
+
+        Ok(read)
+    }
+
+    /// Snapshots the position of every delegator-validator pair in `evm_audit`.
+    /// Only positions that changed since the latest snapshot are written.
+    pub async fn snapshot_positions(&self) -> Result<u64> {
+        let block_num = self.caller.provider.get_block_number().await?.as_u64();
+        let mut pairs = vec![];
+        for (validator, delegator) in self.caller.storage.get_delegation_pairs().await? {
+            match (validator.parse::<Address>(), delegator.parse::<Address>()) {
+                (Ok(v), Ok(d)) => pairs.push((v, d)),
+                _ => error!("Invalid pair {} {}", validator, delegator),
+            }
+        }
+
+        let calls = pairs
+            .iter()
+            .map(|(v, d)| self.caller.staking.delegators(*v, *d))
+            .collect();
+        let amounts = self.read_all(block_num, calls).await?;
+
+        let latest = self.caller.storage.get_latest_positions().await?;
+        let mut positions = vec![];
+        let mut complete = true;
+        for ((v, d), amount) in pairs.into_iter().zip(amounts) {
+            let Some((bound, unbound)) = amount else {
+                error!("Skip position of {:?} at {:?} at block {}", d, v, block_num);
+                complete = false;
+                continue;
+            };
+            let position = DelegatorPosition {
+                validator: encode_prefixed(v.as_bytes()),
+                delegator: encode_prefixed(d.as_bytes()),
+                bound: BigDecimal::from_str(&bound.to_string()).unwrap_or_default(),
+                unbound: BigDecimal::from_str(&unbound.to_string()).unwrap_or_default(),
+            };
+            let key = (position.validator.clone(), position.delegator.clone());
+            if let Some(prev) = latest.get(&key) {
+                if prev.bound == position.bound && prev.unbound == position.unbound {
+                    continue;
+                }
+            }
+            positions.push(position);
+        }
+
+        let tm = self.caller.block_time(block_num).await?;
+        self.caller
+            .storage
+            .insert_positions(block_num as i64, tm, &positions, complete)
+            .await?;
+
         Ok(block_num)
     }
 
-    pub async fn run(&self) -> Result<()> {
-        loop {
-            let list = self.caller.staking.get_validators_list().call().await?;
-            let validators = list.into_iter().map(|v| v.addr).collect::<Vec<H160>>();
-            if validators.len() > 0 {
-                match self.update_validators(validators).await {
-                    Ok(block_num) => {
-                        info!("Update validators at block {} complete", block_num);
+    /// Replays validator snapshots at every `step` blocks in `[start,end]`.
+    /// Blocks already backfilled are skipped, a snapshot is only written if it differs from the
+    /// one before it, and the cache of latest snapshots used by `run` is left untouched.
+    pub async fn backfill(&self, start: u64, end: u64, step: u64) -> Result<()> {
+        let mut block_num = start;
+        while block_num <= end {
+            if !self.caller.storage.is_backfilled(block_num as i64).await? {
+                match self.backfill_block(block_num).await {
+                    Ok(count) => info!("Backfill {} validators at block {}", count, block_num),
+                    Err(e) => error!("Backfill block {} error: {:?}", block_num, e),
+                }
+            }
+            block_num += step;
+        }
+
+        Ok(())
+    }
+
+    async fn backfill_block(&self, block_num: u64) -> Result<usize> {
+        let staking = &self.caller.staking;
+        let call = staking.get_validators_list().block(block_num);
+        let list = with_retries(self.caller.retries, || call.call()).await?;
+        let validators = list.into_iter().map(|v| v.addr).collect::<Vec<H160>>();
+        // Only the partition of a past block, the ones ahead of it may be long retired.
+        self.ensure_partitions(block_num, 0).await?;
+
+        let vdata = self
+            .read_all(
+                block_num,
+                validators.iter().map(|v| staking.validators(*v)).collect(),
+            )
+            .await?;
+        let vstatus = self
+            .read_all(
+                block_num,
+                validators
+                    .iter()
+                    .map(|v| staking.validator_status(*v))
+                    .collect(),
+            )
+            .await?;
+
+        let mut complete = true;
+        for ((vaddr, vdata), vstatus) in validators.iter().zip(vdata).zip(vstatus) {
+            let (Some(vdata), Some(vstatus)) = (vdata, vstatus) else {
+                error!("Skip validator {:?} at block {}", vaddr, block_num);
+                complete = false;
+                continue;
+            };
+            let mut validator = ContractValidator {
+                block_num: block_num as i64,
+                ..Default::default()
+            };
+            validator.set_data(vdata);
+            validator.set_status(vstatus);
+
+            let addr = encode_prefixed(vaddr.as_bytes());
+            let prev = self
+                .caller
+                .storage
+                .get_validator_at(&addr, block_num as i64)
+                .await?;
+            if prev.is_some_and(|p| p.same_state(&validator)) {
+                continue;
+            }
+            self.caller
+                .storage
+                .insert_validator_snapshot(&addr, &validator)
+                .await?;
+        }
+
+        if complete {
+            let tm = self.caller.block_time(block_num).await?;
+            self.caller
+                .storage
+                .mark_backfilled(block_num as i64, validators.len() as i32, tm)
+                .await?;
+        }
+
+        Ok(validators.len())
+    }
+
+    /// Reads `calls` at `block_num`, through Multicall when configured.
+    /// A read that still fails after retries is `None`.
+    async fn read_all<D>(
+        &self,
+        block_num: u64,
+        calls: Vec<ContractCall<Provider<Http>, D>>,
+    ) -> Result<Vec<Option<D>>>
+    where
+        D: Tokenizable + Detokenize + Clone + Send + Sync + 'static,
+    {
+        if let Some(ref mc) = self.caller.multicall {
+            match self.read_all_batched(mc, block_num, &calls).await {
+                Ok(values) => return Ok(values),
+                Err(e) => warn!(
+                    "Multicall reads at block {} failed, reading directly: {:?}",
+                    block_num, e
+                ),
+            }
+        }
+        self.read_all_pooled(block_num, &calls).await
+    }
+
+    async fn read_all_batched<D>(
+        &self,
+        mc: &MulticallBatch,
+        block_num: u64,
+        calls: &[ContractCall<Provider<Http>, D>],
+    ) -> Result<Vec<Option<D>>>
+    where
+        D: Tokenizable + Detokenize + Clone + Send + Sync + 'static,
+    {
+        let mut values = vec![];
+        for chunk in calls.chunks(mc.batch.max(1)) {
+            let mut multicall = mc.multicall.clone().block(block_num);
+            multicall.clear_calls();
+            for call in chunk {
+                multicall.add_call(call.clone(), true);
+            }
+            let results = with_retries(self.caller.retries, || multicall.call_raw()).await?;
+            for (r, call) in results.into_iter().zip(chunk) {
+                match r.map(D::from_token) {
+                    Ok(Ok(v)) => values.push(Some(v)),
+                    Ok(Err(e)) => {
+                        error!("Read {} error: {:?}", call.function.name, e);
+                        values.push(None)
                     }
                     Err(e) => {
-                        error!("Update validators error: {:?}", e);
+                        error!("Read {} reverted: {}", call.function.name, e);
+                        values.push(None)
+                    }
+                }
+            }
+        }
+
+        Ok(values)
+    }
+
+    async fn read_all_pooled<D>(
+        &self,
+        block_num: u64,
+        calls: &[ContractCall<Provider<Http>, D>],
+    ) -> Result<Vec<Option<D>>>
+    where
+        D: Tokenizable + Detokenize + Clone + Send + Sync + 'static,
+    {
+        let mut values = vec![];
+        for chunk in calls.chunks(num_cpus::get().max(1) * 4) {
+            let handles = chunk
+                .iter()
+                .map(|call| {
+                    let call = call.clone().block(block_num);
+                    let retries = self.caller.retries;
+                    tokio::spawn(async move {
+                        let r = with_retries(retries, || call.call()).await;
+                        r.map_err(|e| format!("Read {} error: {:?}", call.function.name, e))
+                    })
+                })
+                .collect::<Vec<_>>();
+            for h in handles {
+                match h.await? {
+                    Ok(v) => values.push(Some(v)),
+                    Err(e) => {
+                        error!("{}", e);
+                        values.push(None)
                     }
                 }
             }
+        }
+
+        Ok(values)
+    }
+
+    /// Snapshots the reward accounting of every validator with delegators in `evm_audit`.
+    pub async fn snapshot_rewards(&self) -> Result<u64> {
+        let block_num = self.caller.provider.get_block_number().await?.as_u64();
+        let mut delegators: HashMap<Address, Vec<Address>> = HashMap::new();
+        for (validator, delegator) in self.caller.storage.get_delegation_pairs().await? {
+            match (validator.parse::<Address>(), delegator.parse::<Address>()) {
+                (Ok(v), Ok(d)) => delegators.entry(v).or_default().push(d),
+                _ => error!("Invalid pair {} {}", validator, delegator),
+            }
+        }
+
+        let commissions = self.caller.storage.get_latest_commissions().await?;
+        let claimed = self
+            .caller
+            .storage
+            .get_claimed_rewards(block_num as i64)
+            .await?;
+
+        // Two passes over all validators: the ratios, then every commission and unclaimed amount.
+        let reward = &self.caller.reward;
+        let validators = delegators.into_iter().collect::<Vec<_>>();
+        let ratio_calls = validators
+            .iter()
+            .map(|(v, _)| reward.acc_per_share_info(*v))
+            .collect();
+        let mut amount_calls = vec![];
+        let mut ranges = vec![];
+        for (v, delegators) in &validators {
+            let start = amount_calls.len();
+            for chunk in delegators.chunks(COMMISSION_DELEGATORS_PER_CALL) {
+                amount_calls.push(reward.commission_unclaimed(*v, chunk.to_vec()));
+            }
+            let mid = amount_calls.len();
+            amount_calls.extend(delegators.iter().map(|d| reward.unclaimed(vec![*v], *d)));
+            ranges.push((start..mid, mid..amount_calls.len()));
+        }
+        let ratios = self.read_all(block_num, ratio_calls).await?;
+        let amounts = self.read_all(block_num, amount_calls).await?;
+        // Sum of the amounts in `range`, `None` if one of the reads failed.
+        let sum = |range: Range<usize>| {
+            amounts[range]
+                .iter()
+                .try_fold(U256::zero(), |acc, a| a.map(|a| acc.saturating_add(a)))
+        };
+
+        let tm = self.caller.block_time(block_num).await?;
+        let mut snapshots = vec![];
+        for (((validator, delegators), ratio), (commission, unclaimed)) in
+            validators.iter().zip(ratios).zip(ranges)
+        {
+            let (
+                Some((staker_ratio, delegator_ratio)),
+                Some(commission),
+                Some(delegators_unclaimed),
+            ) = (ratio, sum(commission), sum(unclaimed))
+            else {
+                error!("Skip rewards of {:?} at block {}", validator, block_num);
+                continue;
+            };
+            let validator = encode_prefixed(validator.as_bytes());
+            let commission_unclaimed =
+                BigDecimal::from_str(&commission.to_string()).unwrap_or_default();
+            let delegators_unclaimed =
+                BigDecimal::from_str(&delegators_unclaimed.to_string()).unwrap_or_default();
+            let commission_earned =
+                commission_earned(commissions.get(&validator), &commission_unclaimed);
+            let delegators_earned =
+                &delegators_unclaimed + claimed.get(&validator).cloned().unwrap_or_default();
+
+            snapshots.push(RewardSnapshot {
+                validator,
+                staker_reward_ratio: BigDecimal::from_str(&staker_ratio.to_string())
+                    .unwrap_or_default(),
+                delegator_reward_ratio: BigDecimal::from_str(&delegator_ratio.to_string())
+                    .unwrap_or_default(),
+                commission_unclaimed,
+                delegators_unclaimed,
+                delegators: delegators.len() as i32,
+                commission_earned,
+                delegators_earned,
+            });
+        }
+
+        self.caller
+            .storage
+            .insert_reward_snapshots(block_num as i64, tm, &snapshots)
+            .await?;
+
+        Ok(block_num)
+    }
+
+    pub async fn run_positions(&self) -> Result<()> {
+        loop {
+            match self.snapshot_positions().await {
+                Ok(block_num) => info!("Snapshot positions at block {} complete", block_num),
+                Err(e) => error!("Snapshot positions error: {:?}", e),
+            }
+            match self.snapshot_rewards().await {
+                Ok(block_num) => info!("Snapshot rewards at block {} complete", block_num),
+                Err(e) => error!("Snapshot rewards error: {:?}", e),
+            }
 
-            tokio::time::sleep(Duration::from_secs(DEFAULT_INTERVAL)).await
+            tokio::time::sleep(Duration::from_secs(self.positions.interval)).await
         }
     }
+
+    /// Stores new entries of the undelegation queue and marks the ones below its start as released.
+    pub async fn reconcile_undelegations(&self, block_num: u64) -> Result<()> {
+        let staking = &self.caller.staking;
+        let retries = self.caller.retries;
+        let call = staking.undelegations_start().block(block_num);
+        let start = with_retries(retries, || call.call()).await?.as_u64() as i64;
+        let call = staking.undelegations_end().block(block_num);
+        let end = with_retries(retries, || call.call()).await?.as_u64() as i64;
+
+        let stored = self
+            .caller
+            .storage
+            .get_queued_undelegations(start, end)
+            .await?;
+        let missing = (start..end)
+            .filter(|i| !stored.contains(i))
+            .collect::<Vec<_>>();
+        let calls = missing
+            .iter()
+            .map(|idx| staking.undelegations(U256::from(*idx)))
+            .collect();
+        let entries = self.read_all(block_num, calls).await?;
+        // Entries that failed to read are still missing on the next cycle.
+        for (idx, entry) in missing.into_iter().zip(entries) {
+            let Some((validator, delegator, amount, unlock_time)) = entry else {
+                error!("Skip undelegation {} at block {}", idx, block_num);
+                continue;
+            };
+            self.caller
+                .storage
+                .insert_queued_undelegation(
+                    idx,
+                    &encode_prefixed(validator.as_bytes()),
+                    &encode_prefixed(delegator.as_bytes()),
+                    BigDecimal::from_str(&amount.to_string()).unwrap_or_default(),
+                    unlock_time.as_u64() as i64,
+                )
+                .await?;
+        }
+
+        let released = self
+            .caller
+            .storage
+            .release_undelegations(start, block_num as i64)
+            .await?;
+        if released > 0 {
+            info!("Released {} undelegations at block {}", released, block_num);
+        }
+
+        Ok(())
+    }
+
+    /// Records network-wide figures at `block_num`.
+    /// Validator counts come from the snapshots just taken of `validators`.
+    pub async fn record_network_stats(
+        &self,
+        block_num: u64,
+        validators: &[(H160, U256)],
+    ) -> Result<()> {
+        let total_delegation = self
+            .caller
+            .staking
+            .total_delegation_amount()
+            .block(block_num)
+            .call()
+            .await?;
+        let global_pre_issue = self
+            .caller
+            .reward
+            .global_pre_issue_amount()
+            .block(block_num)
+            .call()
+            .await?;
+        let return_rate = self
+            .caller
+            .reward
+            .get_delegator_return_rate()
+            .block(block_num)
+            .call()
+            .await?;
+
+        let active = {
+            let snapshots = self.caller.snapshots.lock().unwrap();
+            validators
+                .iter()
+                .filter(|(v, _)| snapshots.get(v).map(|s| s.active).unwrap_or(false))
+                .count()
+        };
+        let total_power = validators
+            .iter()
+            .fold(U256::zero(), |acc, (_, power)| acc.saturating_add(*power));
+        let tm = self.caller.block_time(block_num).await?;
+
+        self.caller
+            .storage
+            .insert_network_stats(NetworkStats {
+                block_num: block_num as i64,
+                tm,
+                total_delegation: BigDecimal::from_str(&total_delegation.to_string())
+                    .unwrap_or_default(),
+                global_pre_issue: BigDecimal::from_str(&global_pre_issue.to_string())
+                    .unwrap_or_default(),
+                return_rate: BigDecimal::from_str(&return_rate.to_string()).unwrap_or_default(),
+                active_validators: active as i32,
+                inactive_validators: (validators.len() - active) as i32,
+                total_power: BigDecimal::from_str(&total_power.to_string()).unwrap_or_default(),
+            })
+            .await
+    }
+
+    pub async fn run(&self, interval: Duration) -> Result<()> {
+        let mut last_stats: Option<Instant> = None;
+        loop {
+            // A failing node shouldn't end the updater, the next cycle retries.
+            if let Err(e) = self.update_cycle(&mut last_stats).await {
+                error!("Update cycle error: {:?}", e);
+            }
+
+            tokio::time::sleep(interval).await
+        }
+    }
+
+    async fn update_cycle(&self, last_stats: &mut Option<Instant>) -> Result<()> {
+        let block_num = self.caller.provider.get_block_number().await?.as_u64();
+        let list = self
+            .caller
+            .staking
+            .get_validators_list()
+            .block(block_num)
+            .call()
+            .await?;
+        let powers = list.iter().map(|v| (v.addr, v.power)).collect::<Vec<_>>();
+        let validators = list.into_iter().map(|v| v.addr).collect::<Vec<H160>>();
+        if validators.is_empty() {
+            return Ok(());
+        }
+
+        let block_num = self.update_validators(block_num, validators).await?;
+        info!("Update validators at block {} complete", block_num);
+        if let Err(e) = self.apply_retention(block_num).await {
+            error!("Apply retention error: {:?}", e);
+        }
+        if let Err(e) = self.reconcile_undelegations(block_num).await {
+            error!("Reconcile undelegations error: {:?}", e);
+        }
+        let interval = Duration::from_secs(self.stats.interval);
+        if last_stats.is_none_or(|t| t.elapsed() >= interval) {
+            match self.record_network_stats(block_num, &powers).await {
+                Ok(()) => *last_stats = Some(Instant::now()),
+                Err(e) => error!("Record network stats error: {:?}", e),
+            }
+        }
+
+        Ok(())
+    }
 }
 
-async fn update_validator_task(
-    caller: Arc<RpcCaller>,
+#[derive(Deserialize)]
+struct ValidatorNotification {
+    block_num: i64,
+    validators: Vec<String>,
+}
+
+fn collect_notified(validators: &mut BTreeSet<Address>, payload: &str) {
+    match serde_json::from_str::<ValidatorNotification>(payload) {
+        Ok(n) => {
+            for v in n.validators {
+                match v.parse::<Address>() {
+                    Ok(addr) => {
+                        validators.insert(addr);
+                    }
+                    Err(_) => error!("Invalid validator {} notified at block {}", v, n.block_num),
+                }
+            }
+        }
+        Err(e) => error!("Invalid notification {}: {:?}", payload, e),
+    }
+}
+
+/// Range for a new partition holding `block`, aligned to `size` and clipped to its neighbours.
+/// Returns `None` if `block` is already covered.
+fn partition_range(partitions: &[Partition], block: i64, size: i64) -> Option<(i64, i64)> {
+    if partitions
+        .iter()
+        .any(|p| p.lower <= block && block < p.upper)
+    {
+        return None;
+    }
+
+    let mut lower = block - block.rem_euclid(size);
+    let mut upper = lower + size;
+    for p in partitions {
+        if p.upper <= block {
+            lower = lower.max(p.upper);
+        }
+        if p.lower > block {
+            upper = upper.min(p.lower);
+        }
+    }
+
+    Some((lower, upper))
+}
+
+/// Validators whose newest snapshot lies in `p`, they would have none left without it.
+fn newest_in_partition(latest: &HashMap<String, i64>, p: &Partition) -> Vec<String> {
+    let mut addrs = latest
+        .iter()
+        .filter(|(_, block)| p.lower <= **block && **block < p.upper)
+        .map(|(addr, _)| addr.clone())
+        .collect::<Vec<String>>();
+    addrs.sort();
+    addrs
+}
+
+/// Commission earned up to a snapshot with `unclaimed`, given the latest stored (unclaimed, earned).
+/// A drop of the unclaimed commission is a claim, what is left was earned since.
+fn commission_earned(
+    prev: Option<&(BigDecimal, BigDecimal)>,
+    unclaimed: &BigDecimal,
+) -> BigDecimal {
+    match prev {
+        Some((prev_unclaimed, earned)) if unclaimed >= prev_unclaimed => {
+            earned + (unclaimed - prev_unclaimed)
+        }
+        Some((_, earned)) => earned + unclaimed,
+        None => unclaimed.clone(),
+    }
+}
+
+/// Calls `f` until it succeeds, at most `retries` more times after the first failure.
+async fn with_retries<T, E, F, Fut>(retries: usize, mut f: F) -> std::result::Result<T, E>
+where
+    E: std::fmt::Debug,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = std::result::Result<T, E>>,
+{
+    let mut attempt = 0;
+    loop {
+        match f().await {
+            Ok(v) => return Ok(v),
+            Err(e) if attempt < retries => {
+                attempt += 1;
+                warn!("Attempt {} failed: {:?}, retrying", attempt, e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Reads a full snapshot of `vaddr` at `block_num`.
+/// Returns `None` if either read still fails after retries, so no partial row is written.
+async fn read_validator(
+    caller: &RpcCaller,
     block_num: u64,
     vaddr: Address,
-) -> Result<()> {
-    let mut validator = ContractValidator::default();
-    validator.block_num = block_num as i64;
-    //    struct ValidatorData {
-    //         bytes publicKey;
-    //         PublicKeyType ty;
-    //         uint256 rate;
-    //         address staker;
-    //         uint256 power;
-    //         uint256 totalUnboundAmount;
-    //         uint256 punishRate;
-    //         uint256 beginBlock;
-    //     }
-    match caller.staking.validators(vaddr).call().await {
-        Ok(vdata) => {
-            validator.pubkey = vdata.0.to_string();
-            validator.pubkey_type = vdata.1 as i32;
-            validator.rate = BigDecimal::from_str(&vdata.2.to_string()).unwrap_or_default();
-            validator.staker = encode_prefixed(&vdata.3.as_bytes());
-            validator.power = BigDecimal::from_str(&vdata.4.to_string()).unwrap_or_default();
-            validator.total_unbound_amount =
-                BigDecimal::from_str(&vdata.5.to_string()).unwrap_or_default();
-            validator.punish_rate = BigDecimal::from_str(&vdata.6.to_string()).unwrap_or_default();
-            validator.begin_block = vdata.7.as_u64() as i64;
-        }
+) -> Option<ContractValidator> {
+    let mut validator = ContractValidator {
+        block_num: block_num as i64,
+        ..Default::default()
+    };
+    let call = caller.staking.validators(vaddr).block(block_num);
+    let vdata = with_retries(caller.retries, || call.call()).await;
+    match vdata {
+        Ok(vdata) => validator.set_data(vdata),
         Err(e) => {
-            error!(" Get data of validator {:?} error: {:?}", vaddr, e)
-        }
-    }
-
-    //  struct ValidatorStatus {
-    //         uint256 heapIndexOff1;
-    //         bool isActive;
-    //         bool jailed;
-    //         uint64 unjailDatetime;
-    //         uint16 shouldVote;
-    //         uint16 voted;
-    //  }
-    match caller.staking.validator_status(vaddr).call().await {
-        Ok(vstatus) => {
-            validator.active = vstatus.1;
-            validator.jailed = vstatus.2;
-            let tm = DateTime::from_timestamp(vstatus.3 as i64, 0)
-                .unwrap()
-                .naive_utc();
-            validator.unjail_datetime = tm;
-            validator.should_vote = vstatus.4 as i32;
-            validator.voted = vstatus.5 as i32;
+            error!(" Get data of validator {:?} error: {:?}", vaddr, e);
+            return None;
         }
+    }
+    let call = caller.staking.validator_status(vaddr).block(block_num);
+    let vstatus = with_retries(caller.retries, || call.call()).await;
+    match vstatus {
+        Ok(vstatus) => validator.set_status(vstatus),
         Err(e) => {
-            error!("Get status of validator {:?} error: {:?}", vaddr, e)
+            error!("Get status of validator {:?} error: {:?}", vaddr, e);
+            return None;
         }
     }
 
+    Some(validator)
+}
+
+async fn update_validator_task(
+    caller: Arc<RpcCaller>,
+    block_num: u64,
+    vaddr: Address,
+) -> Result<Option<Address>> {
+    match read_validator(&caller, block_num, vaddr).await {
+        Some(validator) => {
+            persist_validator(&caller, vaddr, validator).await?;
+            Ok(Some(vaddr))
+        }
+        None => {
+            error!("Skip validator {:?} at block {}", vaddr, block_num);
+            Ok(None)
+        }
+    }
+}
+
+/// Writes `validator` unless it is the same as the latest persisted snapshot.
+async fn persist_validator(
+    caller: &RpcCaller,
+    vaddr: Address,
+    validator: ContractValidator,
+) -> Result<()> {
+    // Held until the cache is updated, so concurrent refreshes compare against each other's writes.
+    let lock = caller.persist_lock(vaddr);
+    let _guard = lock.lock().await;
+    let prev = caller.last_snapshot(vaddr).await?;
+    if let Some(prev) = prev.as_ref().filter(|p| p.same_state(&validator)) {
+        // Loaded from the db after a restart, cache it for the stats of `run`.
+        caller
+            .snapshots
+            .lock()
+            .unwrap()
+            .entry(vaddr)
+            .or_insert_with(|| prev.clone());
+        return Ok(());
+    }
+
+    let addr = encode_prefixed(vaddr.as_bytes());
     caller
         .storage
-        .insert_validator(
-            validator.block_num,
-            &encode_prefixed(vaddr.as_bytes()),
-            &validator.pubkey,
-            validator.pubkey_type,
-            validator.rate,
-            &validator.staker,
-            validator.power,
-            validator.total_unbound_amount,
-            validator.punish_rate,
-            validator.begin_block,
-            validator.active,
-            validator.jailed,
-            validator.unjail_datetime,
-            validator.should_vote,
-            validator.voted,
-        )
+        .insert_validator_snapshot(&addr, &validator)
         .await?;
+    // A snapshot read at an earlier block than the latest one, e.g. by a refresh that finished
+    // after a newer one, is history and no transition.
+    if let Some(prev) = prev.filter(|p| p.block_num < validator.block_num) {
+        let events = prev.transitions(&validator, &caller.power_threshold);
+        if !events.is_empty() {
+            let tm = caller.block_time(validator.block_num as u64).await?;
+            caller
+                .storage
+                .insert_validator_events(validator.block_num, tm, &addr, &events)
+                .await?;
+        }
+    }
+    let mut snapshots = caller.snapshots.lock().unwrap();
+    if snapshots
+        .get(&vaddr)
+        .is_none_or(|p| p.block_num <= validator.block_num)
+    {
+        snapshots.insert(vaddr, validator);
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retention_keeps_unchanged_validators() {
+        let p = Partition {
+            name: "evm_validators_0".into(),
+            lower: 0,
+            upper: 100,
+        };
+        // Snapshots are only written on change: "a" changed again at 150, "b" never did after 5.
+        let latest = HashMap::from([
+            ("0xa".to_string(), 150),
+            ("0xb".to_string(), 5),
+            ("0xc".to_string(), 99),
+            ("0xd".to_string(), 100),
+        ]);
+        assert_eq!(newest_in_partition(&latest, &p), vec!["0xb", "0xc"]);
+    }
+
+    #[test]
+    fn commission_earned_survives_claims() {
+        let n = BigDecimal::from;
+        assert_eq!(commission_earned(None, &n(5)), n(5));
+        assert_eq!(commission_earned(Some(&(n(5), n(5))), &n(8)), n(8));
+        // Claimed 8, then earned 2 more.
+        assert_eq!(commission_earned(Some(&(n(8), n(8))), &n(2)), n(10));
+        assert_eq!(commission_earned(Some(&(n(2), n(10))), &n(2)), n(10));
+    }
+}